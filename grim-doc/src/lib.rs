@@ -34,21 +34,35 @@
 
 # Backus-Naur form
 ```skip
-NUMBER         -> ( 0..9 )* ;
-CHAR           -> "'" * "'" ;
-STRING         -> "\"" ( CHAR )* "\"" ;
+NUMBER         -> ( DIGITS ( "." DIGITS )? ( ( "e" | "E" ) ( "+" | "-" )? DIGITS )?
+                  | "0x" ( 0..9 | "a".."f" | "A".."F" | "_" )*
+                  | "0b" ( 0..1 | "_" )* ) ( "i64" )? ;
+DIGITS         -> ( 0..9 | "_" )* ;
+CHAR           -> "'" ( * | ESCAPE ) "'" ;
+ESCAPE         -> "\\" ( "n" | "t" | "r" | "0" | "\\" | "'" | "\"" | "$"
+                  | "u{" ( 0..9 | "a".."f" | "A".."F" )* "}" ) ;
+STRING         -> "\"" ( CHAR | "${" expression "}" )* "\"" ;
 IDENTIFIER     -> ( "a".."z" | "A".."Z" | NUMBER | "_" )* ;
 
 program        -> ( ( variable | typedef ) ";" )?* "main () {" ( declaration  )* "}" ;
 
-function       -> "def" IDENTIFIER "(" ( IDETIFIER typeDef )?* ")" type "{" ( expression ";" )* "}" ;
+function       -> "def" IDENTIFIER "(" ( IDENTIFIER ( typeId )? "," )?* ")" ( type )? block ;
 
 statement      -> expressionStmt
                 | printStmt
                 | function
                 | ifStmt
+                | whileStmt
+                | matchStmt
+                | forStmt
+                | breakStmt
+                | continueStmt
+                | returnStmt
+                | block
                 ;
 
+block          -> "{" ( declaration )* "}" ;
+
 declaration    -> varDecl
                 | statement
                 | array
@@ -61,34 +75,57 @@ expression     -> literal
                 | unary
                 | binary
                 | grouping
-                | call ;
-
-ifStmt         -> todo ;
+                | call
+                | field
+                | array
+                | index
+                | method
+                | assignment ;
+
+ifStmt         -> "if" "(" expression ")" statement ( "else" statement )? ;
+whileStmt      -> "while" "(" expression ")" statement ;
+matchStmt      -> "match" "(" expression ")" "{"
+                  ( expression ( "," expression )* "=>" statement )*
+                  ( "else" "=>" statement )? "}" ;
+forStmt        -> "for" "(" ( variable | expressionStmt | ";" )
+                  expression? ";" expression? ")" statement ;
+breakStmt      -> "break" ";" ;
+continueStmt   -> "continue" ";" ;
+returnStmt     -> "return" ( expression )? ";" ;
 call           -> IDENTIFIER "(" ( IDENTIFIER | expression ) "," )?* ")" ;
+field          -> expression "." IDENTIFIER ( "=" expression )? ;
+method         -> expression "." IDENTIFIER "(" ( expression "," )?* ")" ;
+index          -> expression "[" expression "]" ( "=" expression )? ;
+assignment     -> IDENTIFIER ( "=" | "+=" | "-=" | "*=" | "/=" | "%=" | "**="
+                  | "&=" | "|=" | "^=" | "<<=" | ">>=" ) expression ;
 
-variable       -> "bind" IDENTIFIER typeDef "=" expression ";" ;
+variable       -> "bind" IDENTIFIER ( typeId )? ( "=" expression )? ";" ;
 
 typedef        -> "typedef" ( "struct" | "enum" ) IDENTIFIER "{" ( IDENTIFIER typeId ( "," )? )* "};" ;
 
-typeId         -> "-:" ( "int" | "char" | "String" |  "nil" | type
-                       | "[" typeId ";" NUMBER "]"
-                       | ( "struct" | "enum" ) IDINTIFIER ) ;
+typeId         -> "-:" type ;
+
+type           -> ( "int" | "i64" | "float" | "bool" | "char" | "String" |  "nil" | IDENTIFIER
+                  | "[" type ";" NUMBER "]"
+                  | ( "struct" | "enum" ) IDINTIFIER ) ( "?" )? ;
 
 enum           -> "enum" IDENTIFIER "{" ( IDENTIFIER ("=" NUMBER)? ",")* "}" ;
 
 struct         -> "struct" IDINTIFIER "{" ( IDENTIFIER typeId )* "}" ;
 
-array          -> "[" ( CHAR | NUMBER ) ( ".." ( CHAR | NUMBER ) )? "]" ;
+array          -> "[" ( expression ( "," expression )* ( "," )? )? "]"
+                | "[" expression ".." expression "]" ;
 
 grouping       -> "(" expression ")" ;
 
 binary         -> expression operator expression ;
 
-operator       -> "==" | "!=" | "<" | "<=" | ">" | ">="
-                | "+"  | "-"  | "*" | "/" ;
+operator       -> "&&" | "||" | "==" | "!=" | "<" | "<=" | ">" | ">="
+                | "+"  | "-"  | "*" | "/" | "%" | "**"
+                | "&"  | "|"  | "^" | "<<" | ">>" ;
 
-unary          -> ( "-" | "!" ) expression ;
+unary          -> ( "-" | "!" | "~" ) expression ;
 
-literal        -> NUMBER | STRING | "true" | "false" |"nil" ;
+literal        -> NUMBER | CHAR | STRING | "true" | "false" |"nil" ;
 ```
 */
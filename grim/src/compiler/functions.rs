@@ -1,9 +1,24 @@
 use super::{
     rules::{get_rule, Precedence},
-    scanner::TokenType,
-    Parser, Result,
+    scanner::{unescape, TokenType},
+    types::{binary_type, call_type, field_type, method_type, param_types},
+    FunctionKind, Parser, Result, Token,
+};
+use crate::{
+    allocate_object, allocate_string,
+    lang_core::{
+        chunk::OpCode,
+        objects::{
+            EnumPointer, ObjEnum, ObjStruct, ObjectPointer, Pointable, StringPointer, RANGE_MAX,
+        },
+        types::TypeId,
+        Number, Type,
+    },
+};
+use std::{
+    mem,
+    num::{IntErrorKind, ParseIntError},
 };
-use crate::{allocate_string, lang_core::chunk::OpCode};
 pub(super) fn parse_precedence(parser: &mut Parser, precedence: Precedence) -> Result<()> {
     parser.next();
     let Some(prefix_rule) = get_rule(parser.previous.id).prefix else {
@@ -19,58 +34,388 @@ pub(super) fn parse_precedence(parser: &mut Parser, precedence: Precedence) -> R
     Ok(())
 }
 pub(super) fn binary(parser: &mut Parser, _: bool) -> Result<()> {
-    let op_type = parser.previous.id;
+    let operator = parser.previous;
+    let op_type = operator.id;
     let rule = get_rule(op_type);
-    parse_precedence(parser, rule.precedence.add_one())?;
+    // `**` is right associative.
+    match op_type {
+        TokenType::StarStar => parse_precedence(parser, Precedence::Power)?,
+        _ => parse_precedence(parser, rule.precedence.add_one())?,
+    }
+
+    let right = parser.pop_type();
+    let left = parser.pop_type();
+    emit_binary(parser, operator, op_type, &left, &right)
+}
+/// Checks and emits `left op_type right` once both operands are compiled.
+pub(super) fn emit_binary(
+    parser: &mut Parser,
+    operator: Token,
+    op_type: TokenType,
+    left: &TypeId,
+    right: &TypeId,
+) -> Result<()> {
+    let Some(result) = binary_type(op_type, left.non_null(), right.non_null()) else {
+        let message = format!(
+            "Operands of '{}' can't be {} and {}.",
+            operator.extract(),
+            left,
+            right
+        );
+        return parser.error_at(operator, &message);
+    };
+    parser.push_type(result);
 
     let op_code = match op_type {
-        TokenType::BangEqual | TokenType::GreaterEqual | TokenType::LessEqual => {
-            let op = match op_type {
-                TokenType::BangEqual => OpCode::Equal,
-                TokenType::GreaterEqual => OpCode::Less,
-                TokenType::LessEqual => OpCode::Greater,
-                _ => unreachable!(),
-            };
-            parser.emit_bytes(op, OpCode::Not);
-            return Ok(());
-        }
         TokenType::EqualEqual => OpCode::Equal,
+        TokenType::BangEqual => OpCode::NotEqual,
         TokenType::Greater => OpCode::Greater,
+        TokenType::GreaterEqual => OpCode::GreaterEqual,
         TokenType::Less => OpCode::Less,
+        TokenType::LessEqual => OpCode::LessEqual,
         TokenType::Plus => OpCode::Add,
         TokenType::Minus => OpCode::Subtract,
         TokenType::Star => OpCode::Multiply,
         TokenType::Slash => OpCode::Divide,
+        TokenType::Percent => OpCode::Modulo,
+        TokenType::StarStar => OpCode::Power,
+        TokenType::Amp => OpCode::BitAnd,
+        TokenType::Pipe => OpCode::BitOr,
+        TokenType::Caret => OpCode::BitXor,
+        TokenType::LessLess => OpCode::ShiftLeft,
+        TokenType::GreaterGreater => OpCode::ShiftRight,
         _ => unreachable!(),
     };
     parser.emit_byte(op_code);
     Ok(())
 }
-pub(super) fn expression(parser: &mut Parser) -> Result<()> {
-    parse_precedence(parser, Precedence::Assignment)?;
+/// The operator a compound assignment such as `+=` applies.
+pub(super) fn compound_operator(id: TokenType) -> Option<TokenType> {
+    let op_type = match id {
+        TokenType::PlusEqual => TokenType::Plus,
+        TokenType::MinusEqual => TokenType::Minus,
+        TokenType::StarEqual => TokenType::Star,
+        TokenType::SlashEqual => TokenType::Slash,
+        TokenType::PercentEqual => TokenType::Percent,
+        TokenType::StarStarEqual => TokenType::StarStar,
+        TokenType::AmpEqual => TokenType::Amp,
+        TokenType::PipeEqual => TokenType::Pipe,
+        TokenType::CaretEqual => TokenType::Caret,
+        TokenType::LessLessEqual => TokenType::LessLess,
+        TokenType::GreaterGreaterEqual => TokenType::GreaterGreater,
+        _ => return None,
+    };
+    Some(op_type)
+}
+pub(super) fn and(parser: &mut Parser, _: bool) -> Result<()> {
+    let end_jump = parser.emit_jump(OpCode::JumpIfFalse);
+    parser.emit_byte(OpCode::Pop);
+    parse_precedence(parser, Precedence::And)?;
+    parser.patch_jump(end_jump)?;
+    logical_type(parser);
     Ok(())
 }
+pub(super) fn or(parser: &mut Parser, _: bool) -> Result<()> {
+    let else_jump = parser.emit_jump(OpCode::JumpIfFalse);
+    let end_jump = parser.emit_jump(OpCode::Jump);
 
+    parser.patch_jump(else_jump)?;
+    parser.emit_byte(OpCode::Pop);
+
+    parse_precedence(parser, Precedence::Or)?;
+    parser.patch_jump(end_jump)?;
+    logical_type(parser);
+    Ok(())
+}
+/// `&&` and `||` produce one of their operands.
+fn logical_type(parser: &mut Parser) {
+    let right = parser.pop_type();
+    let left = parser.pop_type();
+    parser.push_type(if left == right { left } else { TypeId::Any });
+}
+/// Compiles an expression and returns its static type.
+pub(super) fn expression(parser: &mut Parser) -> Result<TypeId> {
+    parse_precedence(parser, Precedence::Assignment)?;
+    Ok(parser.pop_type())
+}
+
+/// The digits of a number literal without `_` separators or radix prefix,
+/// its radix and whether it carries an `i64` suffix.
+fn number_literal(literal: &str) -> (String, u32, bool) {
+    let literal = literal.replace('_', "");
+    let (literal, long) = match literal.strip_suffix("i64") {
+        Some(literal) => (literal, true),
+        None => (literal.as_str(), false),
+    };
+    let (radix, digits) = match literal.get(..2) {
+        Some("0x" | "0X") => (16, &literal[2..]),
+        Some("0b" | "0B") => (2, &literal[2..]),
+        _ => (10, literal),
+    };
+    (digits.to_string(), radix, long)
+}
+/// Whether a decimal literal's digits make it a float.
+fn is_float(digits: &str, radix: u32, long: bool) -> bool {
+    radix == 10 && !long && digits.contains(['.', 'e', 'E'])
+}
+fn out_of_range(err: &ParseIntError) -> bool {
+    matches!(
+        err.kind(),
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow
+    )
+}
 pub(super) fn number(parser: &mut Parser, _: bool) -> Result<()> {
-    let value: i32 = parser.previous.extract().parse().expect("valid number");
-    parser.emit_constant(value);
+    let (digits, radix, long) = number_literal(parser.previous.extract());
+    // An operator binding tighter than unary minus applies before it.
+    let negative =
+        parser.negative_literal && get_rule(parser.current.id).precedence <= Precedence::Unary;
+    parser.negative_literal = negative;
+    let digits = match negative {
+        true => format!("-{}", digits),
+        false => digits,
+    };
+    let digits = digits.as_str();
+    if is_float(digits, radix, long) {
+        let Ok(value) = digits.parse::<f64>() else {
+            return parser.error("Invalid number literal.");
+        };
+        if value.is_infinite() {
+            return parser.error("Float literal out of range.");
+        }
+        parser.emit_constant(value)?;
+        parser.push_type(TypeId::Float);
+        return Ok(());
+    }
+    if long {
+        match i64::from_str_radix(digits, radix) {
+            Ok(value) => parser.emit_constant(value)?,
+            Err(err) if out_of_range(&err) => {
+                return parser.error("Integer literal out of range for i64.")
+            }
+            Err(_) => return parser.error("Invalid number literal."),
+        }
+        parser.push_type(TypeId::Long);
+        return Ok(());
+    }
+    match i32::from_str_radix(digits, radix) {
+        Ok(value) => parser.emit_constant(value)?,
+        Err(err) if out_of_range(&err) => {
+            return parser.error("Integer literal out of range for int, use an i64 suffix.")
+        }
+        Err(_) => return parser.error("Invalid number literal."),
+    }
+    parser.push_type(TypeId::Number);
+    Ok(())
+}
+/// Compiles the arguments of a call to a function taking `params`, if
+/// known, and returns their static types.
+fn argument_list(parser: &mut Parser, params: &[TypeId]) -> Result<Vec<TypeId>> {
+    let mut args = Vec::new();
+    if !parser.check(TokenType::RightParen) {
+        loop {
+            let start = parser.current_chunk().code.len();
+            let arg = expression(parser)?;
+            let arg = match params.get(args.len()) {
+                Some(param) => parser.widen_literal(start, param, arg),
+                None => arg,
+            };
+            if args.len() == u8::MAX as usize {
+                return parser.error("Can't have more than 255 arguments.");
+            }
+            args.push(arg);
+            if !parser.matches(TokenType::Comma) {
+                break;
+            }
+        }
+    }
+    parser.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
+    Ok(args)
+}
+pub(super) fn call(parser: &mut Parser, _: bool) -> Result<()> {
+    let paren = parser.previous;
+    let params = param_types(parser.types.last().expect("callee type").non_null());
+    let args = argument_list(parser, &params)?;
+    let callee = parser.pop_type();
+    let returns = call_type(parser, paren, callee.non_null(), &args)?;
+    parser.emit_bytes(OpCode::Call, args.len() as u8);
+    parser.push_type(returns);
+    Ok(())
+}
+pub(super) fn dot(parser: &mut Parser, can_assign: bool) -> Result<()> {
+    parser.consume(TokenType::Identifier, "Expect field name after '.'.")?;
+    let field = parser.previous;
+    let name = parser.identifier_constant(field)?;
+    let receiver = parser.pop_type();
+    let receiver = receiver.non_null();
+    let id = if can_assign && parser.matches(TokenType::Equal) {
+        let id = field_type(parser, field, receiver)?;
+        let start = parser.current_chunk().code.len();
+        let value = expression(parser)?;
+        let value = parser.widen_literal(start, &id, value);
+        parser.check_type(field, &id, &value)?;
+        parser.emit_bytes(OpCode::SetField, name);
+        id
+    } else if parser.matches(TokenType::LeftParen) {
+        let args = argument_list(parser, &[])?;
+        let id = method_type(parser, field, receiver, &args)?;
+        parser.emit_bytes(OpCode::Invoke, name);
+        parser.emit_byte(args.len() as u8);
+        id
+    } else {
+        let id = field_type(parser, field, receiver)?;
+        parser.emit_bytes(OpCode::GetField, name);
+        if let TypeId::Typedef(ObjectPointer::Enum(_)) = receiver {
+            let end = parser.current_chunk().code.len();
+            parser.variant = Some((end, allocate_string!(field.extract())));
+        }
+        id
+    };
+    parser.push_type(id);
+    Ok(())
+}
+pub(super) fn array(parser: &mut Parser, _: bool) -> Result<()> {
+    if parser.matches(TokenType::RightBracket) {
+        parser.emit_bytes(OpCode::Array, 0);
+        parser.push_type(TypeId::Array(Box::new(TypeId::Any), 0));
+        return Ok(());
+    }
+    let start = parser.current_chunk().code.len();
+    let mut element = expression(parser)?;
+    if parser.matches(TokenType::DotDot) {
+        return range(parser, start, element);
+    }
+    let mut count: u8 = 1;
+    while parser.matches(TokenType::Comma) {
+        if parser.check(TokenType::RightBracket) {
+            break;
+        }
+        let next = expression(parser)?;
+        element = match (element, next) {
+            (element, next) if element.accepts(&next) && element != TypeId::Any => element,
+            (element, next) if next.accepts(&element) => next,
+            (TypeId::Nil, next) => TypeId::Nullable(Box::new(next)),
+            (element, TypeId::Nil) => TypeId::Nullable(Box::new(element)),
+            (element, next) => {
+                let message = format!(
+                    "Array elements must all have the same type, found {} and {}.",
+                    element, next
+                );
+                return parser.error(&message);
+            }
+        };
+        if count == u8::MAX {
+            return parser.error("Can't have more than 255 array elements.");
+        }
+        count += 1;
+    }
+    parser.consume(TokenType::RightBracket, "Expect ']' after array elements.")?;
+    parser.emit_bytes(OpCode::Array, count);
+    parser.push_type(TypeId::Array(Box::new(element), count as usize));
     Ok(())
 }
+
+/// Compiles the rest of `[start..end]` once `start`, which begins at
+/// `code_start`, has been compiled.
+fn range(parser: &mut Parser, code_start: usize, start: TypeId) -> Result<()> {
+    let dots = parser.previous;
+    let code_end = parser.current_chunk().code.len();
+    let end = expression(parser)?;
+    parser.consume(TokenType::RightBracket, "Expect ']' after range.")?;
+    let element = match (start, end) {
+        (TypeId::Number | TypeId::Any, TypeId::Number) | (TypeId::Number, TypeId::Any) => {
+            TypeId::Number
+        }
+        (TypeId::Char | TypeId::Any, TypeId::Char) | (TypeId::Char, TypeId::Any) => TypeId::Char,
+        (TypeId::Any, TypeId::Any) => TypeId::Any,
+        _ => return parser.error_at(dots, "Range bounds must be two numbers or two chars."),
+    };
+    // The length is only known up front when both bounds are literals.
+    let first = parser.constant_at(code_start, code_end);
+    let code_len = parser.current_chunk().code.len();
+    let last = parser.constant_at(code_end, code_len);
+    let bounds = match (first, last) {
+        (Some(Type::Number(a)), Some(Type::Number(b))) => Some((a as i64, b as i64)),
+        (Some(Type::Char(a)), Some(Type::Char(b))) => Some((a as i64, b as i64)),
+        _ => None,
+    };
+    let id = match bounds {
+        Some((a, b)) if a > b => {
+            return parser.error_at(dots, "Range start is greater than its end.");
+        }
+        Some((a, b)) if (b - a + 1) as usize > RANGE_MAX => {
+            let message = format!("Range of {} elements is too large.", b - a + 1);
+            return parser.error_at(dots, &message);
+        }
+        Some((a, b)) => TypeId::Array(Box::new(element), (b - a + 1) as usize),
+        None => TypeId::Any,
+    };
+    parser.emit_byte(OpCode::Range);
+    parser.push_type(id);
+    Ok(())
+}
+
+pub(super) fn index(parser: &mut Parser, can_assign: bool) -> Result<()> {
+    let bracket = parser.previous;
+    let array = parser.pop_type();
+    let element = match array.non_null() {
+        TypeId::Array(element, _) => (**element).clone(),
+        TypeId::Any => TypeId::Any,
+        _ => {
+            let message = format!("Only arrays can be indexed, found {}.", array);
+            return parser.error_at(bracket, &message);
+        }
+    };
+    let index = expression(parser)?;
+    parser.check_type(bracket, &TypeId::Number, &index)?;
+    parser.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+    if can_assign && parser.matches(TokenType::Equal) {
+        let start = parser.current_chunk().code.len();
+        let value = expression(parser)?;
+        let value = parser.widen_literal(start, &element, value);
+        parser.check_value(bracket, &element, &value)?;
+        parser.emit_byte(OpCode::SetIndex);
+    } else {
+        parser.emit_byte(OpCode::GetIndex);
+    }
+    parser.push_type(element);
+    Ok(())
+}
+
 pub(super) fn grouping(parser: &mut Parser, _: bool) -> Result<()> {
-    expression(parser)?;
+    let id = expression(parser)?;
+    parser.push_type(id);
     parser.consume(TokenType::RightParen, "Expect ')' after expression.")
 }
 
 pub(super) fn unary(parser: &mut Parser, _: bool) -> Result<()> {
-    let operator_id = parser.previous.id;
+    let operator = parser.previous;
+    let operator_id = operator.id;
 
     // Compile the operand
+    parser.negative_literal = operator_id == TokenType::Minus && parser.check(TokenType::Number);
     parse_precedence(parser, Precedence::Unary)?;
+    if mem::take(&mut parser.negative_literal) {
+        // The literal is negated already.
+        return Ok(());
+    }
+    let operand = parser.pop_type();
+    let id = match (operator_id, operand.non_null()) {
+        (TokenType::Bang, _) => TypeId::Bool,
+        (TokenType::Minus, TypeId::Float) | (_, TypeId::Long | TypeId::Any) => {
+            operand.non_null().clone()
+        }
+        (_, operand) => {
+            parser.check_type(operator, &TypeId::Number, operand)?;
+            TypeId::Number
+        }
+    };
+    parser.push_type(id);
 
     // Emit the operator instruction.
     let code = match operator_id {
         TokenType::Bang => OpCode::Not,
         TokenType::Minus => OpCode::Negate,
+        TokenType::Tilde => OpCode::BitNot,
         _ => unreachable!(),
     };
     parser.emit_byte(code);
@@ -78,26 +423,69 @@ pub(super) fn unary(parser: &mut Parser, _: bool) -> Result<()> {
 }
 
 pub(super) fn literal(parser: &mut Parser, _: bool) -> Result<()> {
-    let code = match parser.previous.id {
-        TokenType::False => OpCode::False,
-        TokenType::True => OpCode::True,
-        TokenType::Nil => OpCode::Nil,
+    let (code, id) = match parser.previous.id {
+        TokenType::False => (OpCode::False, TypeId::Bool),
+        TokenType::True => (OpCode::True, TypeId::Bool),
+        TokenType::Nil => (OpCode::Nil, TypeId::Nil),
         _ => unreachable!(),
     };
     parser.emit_byte(code);
+    parser.push_type(id);
+    Ok(())
+}
+
+pub(super) fn char(parser: &mut Parser, _: bool) -> Result<()> {
+    // The scanner already checked the escapes and the length.
+    let value = unescape(parser.previous.contents()).expect("valid character");
+    parser.emit_constant(value.chars().next().expect("one character"))?;
+    parser.push_type(TypeId::Char);
     Ok(())
 }
 
 pub(super) fn string(parser: &mut Parser, _: bool) -> Result<()> {
-    let string = allocate_string!(parser.previous.extract());
-    parser.emit_constant(string);
+    let value = unescape(parser.previous.contents()).expect("valid string");
+    let string = allocate_string!(&value);
+    parser.emit_constant(string)?;
+    parser.push_type(TypeId::String);
     Ok(())
 }
 
-pub(super) fn variable(parser: &mut Parser, can_assign: bool) -> Result<()> {
-    parser.named_variable(parser.previous, can_assign);
+/// Compiles `"a ${x} b"` into its segments and embedded expressions,
+/// joined by a single `BuildString`.
+pub(super) fn interpolation(parser: &mut Parser, _: bool) -> Result<()> {
+    let mut count: u8 = 0;
+    loop {
+        let segment = unescape(parser.previous.contents()).expect("valid string");
+        let ends = parser.previous.id == TokenType::String;
+        // The segment, unless empty, and the expression after it.
+        let parts = !segment.is_empty() as usize + !ends as usize;
+        if count as usize + parts > u8::MAX as usize {
+            return parser.error("Can't have more than 255 parts in a string.");
+        }
+        count += parts as u8;
+        if !segment.is_empty() {
+            let string = allocate_string!(&segment);
+            parser.emit_constant(string)?;
+        }
+        if ends {
+            break;
+        }
+        expression(parser)?;
+        if !parser.matches(TokenType::Interpolation) {
+            parser.consume(
+                TokenType::String,
+                "Expect '}' after interpolated expression.",
+            )?;
+        }
+    }
+    parser.emit_bytes(OpCode::BuildString, count);
+    parser.push_type(TypeId::String);
     Ok(())
 }
+
+pub(super) fn variable(parser: &mut Parser, can_assign: bool) -> Result<()> {
+    parser.named_variable(parser.previous, can_assign)
+}
 pub(super) fn print_statement(parser: &mut Parser) -> Result<()> {
     expression(parser)?;
     parser.consume(TokenType::Semicolon, "Expect ';' after value.")?;
@@ -110,36 +498,540 @@ pub(super) fn expression_statement(parser: &mut Parser) -> Result<()> {
     parser.emit_byte(OpCode::Pop);
     Ok(())
 }
+pub(super) fn block(parser: &mut Parser) -> Result<()> {
+    let mut returns = false;
+    while !parser.check(TokenType::RightBrace) && !parser.check(TokenType::EOF) {
+        declaration(parser)?;
+        returns |= parser.compiler.returns;
+    }
+    parser.compiler.returns = returns;
+    parser.consume(TokenType::RightBrace, "Expect '}' after block.")
+}
+pub(super) fn if_statement(parser: &mut Parser) -> Result<()> {
+    parser.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
+    expression(parser)?;
+    parser.consume(TokenType::RightParen, "Expect ')' after condition.")?;
+
+    let then_jump = parser.emit_jump(OpCode::JumpIfFalse);
+    parser.emit_byte(OpCode::Pop);
+    statement(parser)?;
+    let then_returns = parser.compiler.returns;
+
+    let else_jump = parser.emit_jump(OpCode::Jump);
+    parser.patch_jump(then_jump)?;
+    parser.emit_byte(OpCode::Pop);
+
+    let mut else_returns = false;
+    if parser.matches(TokenType::Else) {
+        statement(parser)?;
+        else_returns = parser.compiler.returns;
+    }
+    parser.compiler.returns = then_returns && else_returns;
+    parser.patch_jump(else_jump)
+}
+/// The enum a match on a value of type `id` has to cover.
+fn match_enum(parser: &Parser, id: &TypeId) -> Option<EnumPointer> {
+    let TypeId::Custom(name) = id else {
+        return None;
+    };
+    match parser.type_of(name.get_ref().expect("valid string")) {
+        TypeId::Typedef(ObjectPointer::Enum(e)) => Some(e),
+        _ => None,
+    }
+}
+pub(super) fn match_statement(parser: &mut Parser) -> Result<()> {
+    let keyword = parser.previous;
+    parser.consume(TokenType::LeftParen, "Expect '(' after 'match'.")?;
+    let value = expression(parser)?;
+    parser.consume(TokenType::RightParen, "Expect ')' after match value.")?;
+    parser.consume(TokenType::LeftBrace, "Expect '{' before match arms.")?;
+
+    // The arms compare against the value in a hidden local.
+    parser.begin_scope();
+    parser.add_local(Token::default())?;
+    parser.mark_initialized();
+    let slot = (parser.compiler.locals.len() - 1) as u8;
+
+    let matched = match_enum(parser, &value);
+    let mut missing: Vec<StringPointer> = match matched {
+        Some(e) => e
+            .get_ref()
+            .expect("valid enum")
+            .variants
+            .iter()
+            .map(|(v, _)| *v)
+            .collect(),
+        None => Vec::new(),
+    };
+    let mut has_else = false;
+    let mut all_return = true;
+    let mut exits = Vec::new();
+    while !parser.check(TokenType::RightBrace) && !parser.check(TokenType::EOF) {
+        if has_else {
+            return parser.error_at_current("The 'else' arm must be the last.");
+        }
+        let mut bodies = Vec::new();
+        let mut next_arm = None;
+        if parser.matches(TokenType::Else) {
+            has_else = true;
+        } else {
+            loop {
+                let start = parser.current;
+                parser.emit_bytes(OpCode::GetLocal, slot);
+                parser.variant = None;
+                let pattern = expression(parser)?;
+                parser.check_type(start, &value, &pattern)?;
+                let end = parser.current_chunk().code.len();
+                if let Some((_, variant)) = parser.variant.take().filter(|(at, _)| *at == end) {
+                    missing.retain(|v| *v != variant);
+                }
+                parser.emit_byte(OpCode::Equal);
+                let skip = parser.emit_jump(OpCode::JumpIfFalse);
+                parser.emit_byte(OpCode::Pop);
+                bodies.push(parser.emit_jump(OpCode::Jump));
+                parser.patch_jump(skip)?;
+                parser.emit_byte(OpCode::Pop);
+                if !parser.matches(TokenType::Comma) {
+                    break;
+                }
+            }
+            next_arm = Some(parser.emit_jump(OpCode::Jump));
+        }
+        for body in bodies {
+            parser.patch_jump(body)?;
+        }
+        parser.consume(TokenType::FatArrow, "Expect '=>' after match pattern.")?;
+        statement(parser)?;
+        all_return &= parser.compiler.returns;
+        exits.push(parser.emit_jump(OpCode::Jump));
+        if let Some(next_arm) = next_arm {
+            parser.patch_jump(next_arm)?;
+        }
+    }
+    parser.consume(TokenType::RightBrace, "Expect '}' after match arms.")?;
+    if let (Some(e), false, false) = (matched, has_else, missing.is_empty()) {
+        let names: Vec<String> = missing.iter().map(|v| v.to_string()).collect();
+        let message = format!(
+            "Match on {} is missing {}.",
+            e.get_ref().expect("valid enum").name,
+            names.join(", ")
+        );
+        return parser.error_at(keyword, &message);
+    }
+    for exit in exits {
+        parser.patch_jump(exit)?;
+    }
+    parser.end_scope();
+    // Without an else, an enum match that got this far covers every variant.
+    parser.compiler.returns = all_return && (has_else || matched.is_some());
+    Ok(())
+}
+pub(super) fn while_statement(parser: &mut Parser) -> Result<()> {
+    let loop_start = parser.current_chunk().code.len();
+    parser.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
+    expression(parser)?;
+    parser.consume(TokenType::RightParen, "Expect ')' after condition.")?;
+
+    let exit_jump = parser.emit_jump(OpCode::JumpIfFalse);
+    parser.emit_byte(OpCode::Pop);
+    parser.begin_loop(loop_start);
+    statement(parser)?;
+    parser.emit_loop(loop_start)?;
+
+    parser.patch_jump(exit_jump)?;
+    parser.emit_byte(OpCode::Pop);
+    // The condition may be false from the start.
+    parser.compiler.returns = false;
+    parser.end_loop()
+}
+pub(super) fn for_statement(parser: &mut Parser) -> Result<()> {
+    parser.begin_scope();
+    parser.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+    if parser.matches(TokenType::Semicolon) {
+        // No initializer.
+    } else if parser.matches(TokenType::Bind) {
+        var_declaration(parser)?;
+    } else {
+        expression_statement(parser)?;
+    }
+
+    let mut loop_start = parser.current_chunk().code.len();
+    let mut exit_jump = None;
+    if !parser.matches(TokenType::Semicolon) {
+        expression(parser)?;
+        parser.consume(TokenType::Semicolon, "Expect ';' after loop condition.")?;
+
+        // Jump out of the loop if the condition is false.
+        exit_jump = Some(parser.emit_jump(OpCode::JumpIfFalse));
+        parser.emit_byte(OpCode::Pop);
+    }
+
+    if !parser.matches(TokenType::RightParen) {
+        let body_jump = parser.emit_jump(OpCode::Jump);
+        let increment_start = parser.current_chunk().code.len();
+        expression(parser)?;
+        parser.emit_byte(OpCode::Pop);
+        parser.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        parser.emit_loop(loop_start)?;
+        loop_start = increment_start;
+        parser.patch_jump(body_jump)?;
+    }
+
+    parser.begin_loop(loop_start);
+    statement(parser)?;
+    parser.emit_loop(loop_start)?;
+
+    if let Some(exit_jump) = exit_jump {
+        parser.patch_jump(exit_jump)?;
+        parser.emit_byte(OpCode::Pop);
+    }
+    parser.end_loop()?;
+    parser.end_scope();
+    parser.compiler.returns = false;
+    Ok(())
+}
+pub(super) fn break_statement(parser: &mut Parser) -> Result<()> {
+    let Some(depth) = parser.compiler.loops.last().map(|lp| lp.scope_depth) else {
+        return parser.error("Can't use 'break' outside of a loop.");
+    };
+    parser.consume(TokenType::Semicolon, "Expect ';' after 'break'.")?;
+    parser.discard_locals(depth);
+    let jump = parser.emit_jump(OpCode::Jump);
+    parser
+        .compiler
+        .loops
+        .last_mut()
+        .expect("enclosing loop")
+        .breaks
+        .push(jump);
+    Ok(())
+}
+pub(super) fn continue_statement(parser: &mut Parser) -> Result<()> {
+    let Some((start, depth)) = parser
+        .compiler
+        .loops
+        .last()
+        .map(|lp| (lp.start, lp.scope_depth))
+    else {
+        return parser.error("Can't use 'continue' outside of a loop.");
+    };
+    parser.consume(TokenType::Semicolon, "Expect ';' after 'continue'.")?;
+    parser.discard_locals(depth);
+    parser.emit_loop(start)
+}
+pub(super) fn return_statement(parser: &mut Parser) -> Result<()> {
+    if parser.compiler.kind == FunctionKind::Script {
+        return parser.error("Can't return from top-level code.");
+    }
+    let keyword = parser.previous;
+    let expected = parser.compiler.return_type.clone();
+    if parser.matches(TokenType::Semicolon) {
+        parser.check_type(keyword, &expected, &TypeId::Nil)?;
+        parser.emit_return();
+    } else {
+        let start = parser.current_chunk().code.len();
+        let value = expression(parser)?;
+        let value = parser.widen_literal(start, &expected, value);
+        parser.check_value(keyword, &expected, &value)?;
+        parser.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
+        parser.emit_byte(OpCode::Return);
+    }
+    parser.compiler.returns = true;
+    Ok(())
+}
 pub(super) fn statement(parser: &mut Parser) -> Result<()> {
+    parser.compiler.returns = false;
     if parser.matches(TokenType::Print) {
         print_statement(parser)
+    } else if parser.matches(TokenType::If) {
+        if_statement(parser)
+    } else if parser.matches(TokenType::While) {
+        while_statement(parser)
+    } else if parser.matches(TokenType::Match) {
+        match_statement(parser)
+    } else if parser.matches(TokenType::For) {
+        for_statement(parser)
+    } else if parser.matches(TokenType::Break) {
+        break_statement(parser)
+    } else if parser.matches(TokenType::Continue) {
+        continue_statement(parser)
+    } else if parser.matches(TokenType::Return) {
+        return_statement(parser)
+    } else if parser.matches(TokenType::LeftBrace) {
+        parser.begin_scope();
+        block(parser)?;
+        parser.end_scope();
+        Ok(())
     } else {
         expression_statement(parser)
     }
 }
 fn parse_variable(parser: &mut Parser, message: &str) -> Result<u8> {
     parser.consume(TokenType::Identifier, message)?;
-    Ok(parser.identifier_constant(parser.previous))
+    parser.declare_variable()?;
+    if parser.compiler.scope_depth > 0 {
+        return Ok(0);
+    }
+    parser.identifier_constant(parser.previous)
 }
 pub(super) fn var_declaration(parser: &mut Parser) -> Result<()> {
     let global = parse_variable(parser, "Expect variable name.")?;
+    let name = parser.previous;
+    let annotation = match parser.matches(TokenType::MinusColon) {
+        true => Some(type_id(parser)?),
+        false => None,
+    };
 
-    if parser.matches(TokenType::Equal) {
-        expression(parser)?;
+    let value = if parser.matches(TokenType::Equal) {
+        let start = parser.current_chunk().code.len();
+        let value = expression(parser)?;
+        match &annotation {
+            Some(id) => parser.widen_literal(start, id, value),
+            None => value,
+        }
     } else {
         parser.emit_byte(OpCode::Nil);
-    }
+        TypeId::Nil
+    };
 
     parser.consume(
         TokenType::Semicolon,
         "Expect ';' after variable declaration.",
     )?;
 
+    // Without an annotation the variable is locked to the type of its
+    // initializer. Only a `T?` annotation lets it start out as nil.
+    let id = match annotation {
+        Some(id) => {
+            parser.check_value(name, &id, &value)?;
+            id
+        }
+        None if value == TypeId::Nil => {
+            return parser.error_at(name, "A variable holding nil needs a nullable type.");
+        }
+        None => value,
+    };
+    parser.set_type(name.extract(), id);
     parser.define_variable(global);
     Ok(())
 }
+fn function(parser: &mut Parser, kind: FunctionKind) -> Result<()> {
+    let name = parser.previous;
+    parser.push_compiler(kind);
+    parser.begin_scope();
+
+    parser.consume(TokenType::LeftParen, "Expect '(' after function name.")?;
+    let mut params = Vec::new();
+    if !parser.check(TokenType::RightParen) {
+        loop {
+            if parser.compiler.function.arity == u8::MAX {
+                return parser.error_at_current("Can't have more than 255 parameters.");
+            }
+            parser.compiler.function.arity += 1;
+            let constant = parse_variable(parser, "Expect parameter name.")?;
+            let param = parser.previous;
+            let id = match parser.matches(TokenType::MinusColon) {
+                true => type_id(parser)?,
+                false => TypeId::Any,
+            };
+            parser.set_type(param.extract(), id.clone());
+            params.push(id);
+            parser.define_variable(constant);
+            if !parser.matches(TokenType::Comma) {
+                break;
+            }
+        }
+    }
+    parser.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+    // Callers that only know the arguments at runtime are checked here.
+    for (slot, id) in params.iter().enumerate() {
+        if *id != TypeId::Any {
+            let id = parser.type_constant(id.clone())?;
+            parser.emit_bytes(OpCode::GetLocal, slot as u8 + 1);
+            parser.emit_bytes(OpCode::CheckType, id);
+            parser.emit_byte(OpCode::Pop);
+        }
+    }
+    let returns = match parser.check(TokenType::LeftBrace) {
+        true => TypeId::Any,
+        false => type_id(parser)?,
+    };
+    parser.compiler.return_type = returns.clone();
+    // Known before the body so that recursive calls are checked too.
+    let signature = TypeId::Fn(params, Box::new(returns));
+    let enclosing = parser
+        .compiler
+        .enclosing
+        .as_mut()
+        .expect("enclosing function");
+    enclosing.set_type(&mut parser.global_types, name.extract(), signature);
+    parser.consume(TokenType::LeftBrace, "Expect '{' before function body.")?;
+    block(parser)?;
+    if !parser.compiler.returns && !parser.compiler.return_type.accepts(&TypeId::Nil) {
+        let message = format!(
+            "Function '{}' can end without returning {}.",
+            name.extract(),
+            parser.compiler.return_type
+        );
+        return parser.error(&message);
+    }
+
+    let (function, upvalues) = parser.end_compiler();
+    let constant = parser.make_constant(function)?;
+    parser.emit_bytes(OpCode::Closure, constant);
+    for upvalue in upvalues {
+        parser.emit_bytes(upvalue.is_local as u8, upvalue.index);
+    }
+    Ok(())
+}
+pub(super) fn fun_declaration(parser: &mut Parser) -> Result<()> {
+    let global = parse_variable(parser, "Expect function name.")?;
+    parser.mark_initialized();
+    function(parser, FunctionKind::Function)?;
+    parser.define_variable(global);
+    Ok(())
+}
+/// Parses the type following a `-:`.
+pub(super) fn type_id(parser: &mut Parser) -> Result<TypeId> {
+    parser.next();
+    let id = match parser.previous.id {
+        TokenType::Int => TypeId::Number,
+        TokenType::Char => TypeId::Char,
+        TokenType::Nil => TypeId::Nil,
+        TokenType::LeftBracket => {
+            let element = type_id(parser)?;
+            parser.consume(TokenType::Semicolon, "Expect ';' after array element type.")?;
+            parser.consume(TokenType::Number, "Expect array length.")?;
+            let Ok(len) = parser.previous.extract().parse() else {
+                return parser.error("Invalid array length.");
+            };
+            parser.consume(TokenType::RightBracket, "Expect ']' after array length.")?;
+            TypeId::Array(Box::new(element), len)
+        }
+        TokenType::Struct | TokenType::Enum => {
+            parser.consume(TokenType::Identifier, "Expect type name.")?;
+            TypeId::Custom(allocate_string!(parser.previous.extract()))
+        }
+        TokenType::Identifier => match parser.previous.extract() {
+            "bool" => TypeId::Bool,
+            "float" => TypeId::Float,
+            "i64" => TypeId::Long,
+            "String" => TypeId::String,
+            name => TypeId::Custom(allocate_string!(name)),
+        },
+        _ => return parser.error("Expect type."),
+    };
+    if parser.matches(TokenType::Question) {
+        return Ok(TypeId::Nullable(Box::new(id)));
+    }
+    Ok(id)
+}
+fn struct_declaration(parser: &mut Parser) -> Result<()> {
+    let global = parse_variable(parser, "Expect struct name.")?;
+    let name = allocate_string!(parser.previous.extract());
+    parser.consume(TokenType::LeftBrace, "Expect '{' after struct name.")?;
+
+    let mut fields = Vec::new();
+    while !parser.check(TokenType::RightBrace) {
+        parser.consume(TokenType::Identifier, "Expect field name.")?;
+        let field = allocate_string!(parser.previous.extract());
+        if fields.iter().any(|(f, _)| *f == field) {
+            return parser.error("Already a field with this name in this struct.");
+        }
+        parser.consume(TokenType::MinusColon, "Expect '-:' after field name.")?;
+        fields.push((field, type_id(parser)?));
+        if !parser.matches(TokenType::Comma) {
+            break;
+        }
+    }
+    parser.consume(TokenType::RightBrace, "Expect '}' after struct fields.")?;
+    parser.consume(TokenType::Semicolon, "Expect ';' after struct declaration.")?;
+
+    let typedef = allocate_object!(ObjStruct::new(name, fields));
+    parser.emit_constant(typedef)?;
+    parser.set_type(
+        name.get_ref().expect("valid string"),
+        TypeId::Typedef(typedef),
+    );
+    parser.define_variable(global);
+    Ok(())
+}
+fn discriminant(parser: &mut Parser) -> Result<Number> {
+    let negative = parser.matches(TokenType::Minus);
+    parser.consume(TokenType::Number, "Expect discriminant value.")?;
+    let (digits, radix, long) = number_literal(parser.previous.extract());
+    if long || is_float(&digits, radix, long) {
+        return parser.error("Discriminant must be an int.");
+    }
+    let digits = match negative {
+        true => format!("-{}", digits),
+        false => digits,
+    };
+    match Number::from_str_radix(&digits, radix) {
+        Ok(value) => Ok(value),
+        Err(err) if out_of_range(&err) => parser.error("Discriminant out of range."),
+        Err(_) => parser.error("Invalid number literal."),
+    }
+}
+fn enum_declaration(parser: &mut Parser) -> Result<()> {
+    let global = parse_variable(parser, "Expect enum name.")?;
+    let name = allocate_string!(parser.previous.extract());
+    parser.consume(TokenType::LeftBrace, "Expect '{' after enum name.")?;
+
+    let mut variants: Vec<(StringPointer, Number)> = Vec::new();
+    // `None` once the previous discriminant was the largest int.
+    let mut next = Some(0);
+    while !parser.check(TokenType::RightBrace) {
+        parser.consume(TokenType::Identifier, "Expect variant name.")?;
+        let token = parser.previous;
+        let variant = allocate_string!(token.extract());
+        if variants.iter().any(|(v, _)| *v == variant) {
+            return parser.error("Already a variant with this name in this enum.");
+        }
+        let value = match (parser.matches(TokenType::Equal), next) {
+            (true, _) => discriminant(parser)?,
+            (false, Some(next)) => next,
+            (false, None) => return parser.error("Discriminant out of range."),
+        };
+        if let Some((other, _)) = variants.iter().find(|(_, v)| *v == value) {
+            let message = format!("Discriminant {} is already used by {}.", value, other);
+            return parser.error_at(token, &message);
+        }
+        variants.push((variant, value));
+        next = value.checked_add(1);
+        if !parser.matches(TokenType::Comma) {
+            break;
+        }
+    }
+    parser.consume(TokenType::RightBrace, "Expect '}' after enum variants.")?;
+    parser.consume(TokenType::Semicolon, "Expect ';' after enum declaration.")?;
+
+    let typedef = allocate_object!(ObjEnum::new(name, variants));
+    parser.emit_constant(typedef)?;
+    parser.set_type(
+        name.get_ref().expect("valid string"),
+        TypeId::Typedef(typedef),
+    );
+    parser.define_variable(global);
+    Ok(())
+}
+pub(super) fn typedef_declaration(parser: &mut Parser) -> Result<()> {
+    if parser.matches(TokenType::Struct) {
+        struct_declaration(parser)
+    } else if parser.matches(TokenType::Enum) {
+        enum_declaration(parser)
+    } else {
+        parser.error_at_current("Expect 'struct' or 'enum' after 'typedef'.")
+    }
+}
 pub(super) fn declaration(parser: &mut Parser) -> Result<()> {
-    if parser.matches(TokenType::Bind) {
+    parser.compiler.returns = false;
+    if parser.matches(TokenType::Typedef) {
+        typedef_declaration(parser)
+    } else if parser.matches(TokenType::Def) {
+        fun_declaration(parser)
+    } else if parser.matches(TokenType::Bind) {
         var_declaration(parser)
     } else {
         statement(parser)
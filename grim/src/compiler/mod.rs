@@ -1,9 +1,10 @@
-use crate::{allocate_string, lang_core::prelude::*};
+use crate::{allocate_object, allocate_string, lang_core::prelude::*};
 
-use std::result;
+use std::{collections::HashMap, mem, result};
 mod functions;
 mod rules;
 pub mod scanner;
+mod types;
 use functions::*;
 
 use self::scanner::TokenType;
@@ -11,18 +12,182 @@ use crate::err::CompilerError;
 pub use scanner::{Scanner, Token};
 pub type Result<T> = result::Result<T, CompilerError>;
 
+const LOCALS_MAX: usize = u8::MAX as usize + 1;
+
+struct Local {
+    name: Token,
+    /// `None` until the variable's initializer has been compiled.
+    depth: Option<usize>,
+    /// Set once a nested function closes over the variable.
+    is_captured: bool,
+    type_id: TypeId,
+}
+
+#[derive(Clone, Copy)]
+struct Upvalue {
+    /// The slot or upvalue index in the enclosing function.
+    index: u8,
+    /// Whether `index` refers to a local of the enclosing function rather
+    /// than one of its upvalues.
+    is_local: bool,
+}
+
+struct Loop {
+    /// Where `continue` jumps back to.
+    start: usize,
+    /// The scope depth just outside the loop body.
+    scope_depth: usize,
+    /// Forward jumps emitted by `break`, patched once the loop ends.
+    breaks: Vec<usize>,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum FunctionKind {
+    Function,
+    Script,
+}
+
+struct Compiler {
+    enclosing: Option<Box<Compiler>>,
+    function: ObjFunction,
+    kind: FunctionKind,
+    locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
+    scope_depth: usize,
+    loops: Vec<Loop>,
+    /// What `return` statements must produce.
+    return_type: TypeId,
+    /// Whether every path through the statement just compiled returns.
+    returns: bool,
+}
+impl Compiler {
+    fn new(kind: FunctionKind) -> Self {
+        Self {
+            enclosing: None,
+            function: ObjFunction::new(),
+            kind,
+            // Slot zero holds the function being called.
+            locals: vec![Local {
+                name: Token::default(),
+                depth: Some(0),
+                is_captured: false,
+                type_id: TypeId::Any,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+            loops: Vec::new(),
+            return_type: TypeId::Any,
+            returns: false,
+        }
+    }
+
+    /// Records the static type of the variable `name` that was just
+    /// declared. The type of a global is staged in `globals`.
+    fn set_type(&mut self, globals: &mut GlobalTypes, name: &str, id: TypeId) {
+        if self.scope_depth > 0 {
+            let local = self.locals.last_mut().expect("declared local");
+            local.type_id = id;
+            return;
+        }
+        globals.insert(allocate_string!(name), id);
+    }
+
+    /// The static type of the variable `name` as seen from this function,
+    /// looking at the staged `globals` before those of earlier compiles.
+    fn type_of(&self, globals: &GlobalTypes, name: &str) -> TypeId {
+        if let Some(local) = self.locals.iter().rev().find(|l| l.name.extract() == name) {
+            return local.type_id.clone();
+        }
+        if let Some(enclosing) = &self.enclosing {
+            return enclosing.type_of(globals, name);
+        }
+        let key = allocate_string!(name);
+        if let Some(id) = globals.get(&key) {
+            return id.clone();
+        }
+        let id = crate::vm::VM.lock().memory.get_global_type(key);
+        id.unwrap_or(TypeId::Any)
+    }
+
+    fn resolve_local(&self, name: &str) -> result::Result<Option<u8>, &'static str> {
+        for (slot, local) in self.locals.iter().enumerate().rev() {
+            if local.name.extract() == name {
+                if local.depth.is_none() {
+                    return Err("Can't read local variable in its own initializer.");
+                }
+                return Ok(Some(slot as u8));
+            }
+        }
+        Ok(None)
+    }
+
+    fn add_upvalue(&mut self, index: u8, is_local: bool) -> result::Result<u8, &'static str> {
+        if let Some(i) = self
+            .upvalues
+            .iter()
+            .position(|u| u.index == index && u.is_local == is_local)
+        {
+            return Ok(i as u8);
+        }
+        if self.upvalues.len() == LOCALS_MAX {
+            return Err("Too many closure variables in function.");
+        }
+        self.upvalues.push(Upvalue { index, is_local });
+        self.function.upvalue_count = self.upvalues.len() as u8;
+        Ok(self.upvalues.len() as u8 - 1)
+    }
+
+    /// Looks `name` up in the enclosing functions, threading an upvalue
+    /// through every function in between.
+    fn resolve_upvalue(&mut self, name: &str) -> result::Result<Option<u8>, &'static str> {
+        let Some(enclosing) = self.enclosing.as_mut() else {
+            return Ok(None);
+        };
+        if let Some(local) = enclosing.resolve_local(name)? {
+            enclosing.locals[local as usize].is_captured = true;
+            return self.add_upvalue(local, true).map(Some);
+        }
+        if let Some(upvalue) = enclosing.resolve_upvalue(name)? {
+            return self.add_upvalue(upvalue, false).map(Some);
+        }
+        Ok(None)
+    }
+}
+
+type GlobalTypes = HashMap<StringPointer, TypeId>;
+
 struct Parser<'a> {
     previous: Token,
     current: Token,
     scanner: Scanner<'a>,
-    chunk: Chunk,
+    compiler: Box<Compiler>,
+    /// The static types of the values the code compiled so far leaves on
+    /// the stack.
+    types: Vec<TypeId>,
+    /// The first token the scanner rejected. Any error after it is most
+    /// likely caused by it, so it is reported instead.
+    scanner_error: Option<CompilerError>,
+    /// The variant the last `Enum.Variant` read named and where its code
+    /// ends, so a match arm can tell which variant it covers.
+    variant: Option<(usize, StringPointer)>,
+    /// Set by a unary minus whose operand starts with a number literal.
+    /// The literal parses itself negated, and leaves this set, when it is
+    /// the whole operand, so that the smallest int is in range.
+    negative_literal: bool,
+    /// The types of the globals declared so far, handed to the vm only once
+    /// the whole source compiles.
+    global_types: GlobalTypes,
 }
 impl Iterator for Parser<'_> {
     type Item = Result<()>;
     fn next(&mut self) -> Option<Self::Item> {
         self.previous = self.current;
         self.current = match self.scanner.next() {
-            Some(Err(err)) => return Some(Err(err.into())),
+            Some(Err(err)) => {
+                let err = CompilerError::from(err);
+                self.scanner_error.get_or_insert_with(|| err.clone());
+                return Some(Err(err));
+            }
             Some(Ok(token)) => token,
             None => return None,
         };
@@ -35,7 +200,12 @@ impl Parser<'_> {
             previous: Token::default(),
             current: Token::default(),
             scanner: Scanner::new(source),
-            chunk: Chunk::new(),
+            compiler: Box::new(Compiler::new(FunctionKind::Script)),
+            types: Vec::new(),
+            scanner_error: None,
+            variant: None,
+            negative_literal: false,
+            global_types: HashMap::new(),
         }
     }
 }
@@ -47,6 +217,9 @@ impl Parser<'_> {
         self.error_at(self.previous, message)
     }
     fn error_at<T>(&self, token: Token, message: &str) -> Result<T> {
+        if let Some(err) = &self.scanner_error {
+            return Err(err.clone());
+        }
         let mut out = match token.id {
             TokenType::EOF => " at end".into(),
             _ => format!(" at '{}'", token.extract()),
@@ -54,6 +227,23 @@ impl Parser<'_> {
         out.push_str(&format!(": {}\n", message));
         Err(CompilerError::new(&out, token.line))
     }
+    /// Reports a compile error at `token` unless `expected` accepts `found`.
+    fn check_type(&self, token: Token, expected: &TypeId, found: &TypeId) -> Result<()> {
+        if expected.accepts(found) {
+            return Ok(());
+        }
+        self.error_at(token, &format!("Expected {}, found {}.", expected, found))
+    }
+    /// Like [`Parser::check_type`] for the value on top of the stack, which
+    /// is also checked at runtime if `found` is only known there.
+    fn check_value(&mut self, token: Token, expected: &TypeId, found: &TypeId) -> Result<()> {
+        self.check_type(token, expected, found)?;
+        if *expected != TypeId::Any && found.has_any() {
+            let id = self.type_constant(expected.clone())?;
+            self.emit_bytes(OpCode::CheckType, id);
+        }
+        Ok(())
+    }
 
     fn synchronize(&mut self) {
         while self.current.id != TokenType::EOF {
@@ -62,9 +252,13 @@ impl Parser<'_> {
             }
 
             match self.current.id {
-                TokenType::If
+                TokenType::Typedef
+                | TokenType::If
                 | TokenType::Def
                 | TokenType::Bind
+                | TokenType::While
+                | TokenType::Match
+                | TokenType::For
                 | TokenType::Print
                 | TokenType::Return => return,
                 _ => {
@@ -83,6 +277,18 @@ impl Parser<'_> {
         }
         self.error_at_current(message)
     }
+    fn set_type(&mut self, name: &str, id: TypeId) {
+        self.compiler.set_type(&mut self.global_types, name, id);
+    }
+    fn type_of(&self, name: &str) -> TypeId {
+        self.compiler.type_of(&self.global_types, name)
+    }
+    fn push_type(&mut self, id: TypeId) {
+        self.types.push(id);
+    }
+    fn pop_type(&mut self) -> TypeId {
+        self.types.pop().expect("expression type")
+    }
     fn emit_byte<T: Into<u8>>(&mut self, byte: T) {
         let line = self.previous.line as u32;
         self.current_chunk().write(byte, line);
@@ -91,58 +297,275 @@ impl Parser<'_> {
         self.emit_byte(byte1.into());
         self.emit_byte(byte2);
     }
-    fn emit_constant<T: Into<Type>>(&mut self, value: T) {
-        let loc = self.current_chunk().constant(value);
+    /// Emits `instruction` with a placeholder 16-bit operand and returns the
+    /// offset of that operand so it can be filled in by [`Parser::patch_jump`].
+    fn emit_jump(&mut self, instruction: OpCode) -> usize {
+        self.emit_byte(instruction);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+        self.current_chunk().code.len() - 2
+    }
+    fn patch_jump(&mut self, offset: usize) -> Result<()> {
+        // -2 to adjust for the bytecode for the jump offset itself.
+        let jump = self.current_chunk().code.len() - offset - 2;
+        if jump > u16::MAX as usize {
+            return self.error("Too much code to jump over.");
+        }
+        let [high, low] = (jump as u16).to_be_bytes();
+        let code = &mut self.current_chunk().code;
+        code[offset] = high;
+        code[offset + 1] = low;
+        Ok(())
+    }
+    fn emit_loop(&mut self, loop_start: usize) -> Result<()> {
+        self.emit_byte(OpCode::Loop);
+        let offset = self.current_chunk().code.len() - loop_start + 2;
+        if offset > u16::MAX as usize {
+            return self.error("Loop body too large.");
+        }
+        let [high, low] = (offset as u16).to_be_bytes();
+        self.emit_bytes(high, low);
+        Ok(())
+    }
+    fn make_constant<T: Into<Type>>(&mut self, value: T) -> Result<u8> {
+        match self.current_chunk().constant(value) {
+            Some(loc) => Ok(loc),
+            None => self.error("Too many constants in one chunk."),
+        }
+    }
+    fn type_constant(&mut self, id: TypeId) -> Result<u8> {
+        match self.current_chunk().type_constant(id) {
+            Some(loc) => Ok(loc),
+            None => self.error("Too many type checks in one function."),
+        }
+    }
+    fn emit_constant<T: Into<Type>>(&mut self, value: T) -> Result<()> {
+        let loc = self.make_constant(value)?;
         self.emit_bytes(OpCode::Constant, loc);
+        Ok(())
     }
 
     fn current_chunk(&mut self) -> &mut Chunk {
-        &mut self.chunk
+        &mut self.compiler.function.chunk
+    }
+    /// The value of the code emitted between `start` and `end` if it is a
+    /// lone constant.
+    fn constant_at(&mut self, start: usize, end: usize) -> Option<Type> {
+        let chunk = self.current_chunk();
+        match chunk.code[start..end] {
+            [op, index] if u8::from(OpCode::Constant) == op => {
+                Some(chunk.constants[index as usize])
+            }
+            _ => None,
+        }
+    }
+
+    /// Widens the value compiled from `start` on to an `expected` i64 or
+    /// float if it is a lone int literal, and returns its type after that.
+    fn widen_literal(&mut self, start: usize, expected: &TypeId, found: TypeId) -> TypeId {
+        let end = self.current_chunk().code.len();
+        let Some(Type::Number(n)) = self.constant_at(start, end) else {
+            return found;
+        };
+        let value: Type = match expected.non_null() {
+            TypeId::Long => (n as i64).into(),
+            TypeId::Float => (n as f64).into(),
+            _ => return found,
+        };
+        let chunk = self.current_chunk();
+        chunk.constants[chunk.code[start + 1] as usize] = value;
+        expected.non_null().clone()
     }
 
     fn emit_return(&mut self) {
-        self.emit_byte(OpCode::Return);
+        self.emit_bytes(OpCode::Nil, OpCode::Return);
     }
 
+    fn check(&self, id: TokenType) -> bool {
+        self.current.id == id
+    }
     fn matches(&mut self, id: TokenType) -> bool {
-        if self.current.id != id {
+        if !self.check(id) {
             return false;
         }
         self.next();
         true
     }
-    fn end_compiler(&mut self) {
+    /// Starts compiling a new function named after the previous token.
+    fn push_compiler(&mut self, kind: FunctionKind) {
+        let name = allocate_string!(self.previous.extract());
+        let enclosing = mem::replace(&mut self.compiler, Box::new(Compiler::new(kind)));
+        self.compiler.enclosing = Some(enclosing);
+        self.compiler.function.name = Some(name);
+    }
+    /// Finishes the current function and hands it over to the vm's memory,
+    /// resuming the enclosing function if there is one. Also returns the
+    /// variables the function captures.
+    fn end_compiler(&mut self) -> (ObjectPointer, Vec<Upvalue>) {
         self.emit_return();
+        let function = mem::take(&mut self.compiler.function);
+        let upvalues = mem::take(&mut self.compiler.upvalues);
         #[cfg(feature = "print_code")]
-        println!("{}", self.current_chunk());
+        println!("== {} ==\n{}", function, function.chunk);
+        if let Some(enclosing) = self.compiler.enclosing.take() {
+            self.compiler = enclosing;
+        }
+        (allocate_object!(function), upvalues)
     }
-    fn identifier_constant(&mut self, name: Token) -> u8 {
+    fn identifier_constant(&mut self, name: Token) -> Result<u8> {
         let string = allocate_string!(name.extract());
-        self.current_chunk().constant(string)
+        self.make_constant(string)
+    }
+    fn begin_scope(&mut self) {
+        self.compiler.scope_depth += 1;
+    }
+    fn end_scope(&mut self) {
+        self.compiler.scope_depth -= 1;
+        while let Some(local) = self.compiler.locals.last() {
+            if local.depth.is_some_and(|d| d <= self.compiler.scope_depth) {
+                break;
+            }
+            if local.is_captured {
+                self.emit_byte(OpCode::CloseUpvalue);
+            } else {
+                self.emit_byte(OpCode::Pop);
+            }
+            self.compiler.locals.pop();
+        }
+    }
+    /// Emits a `Pop` for every local declared deeper than `depth` without
+    /// removing them from the scope table.
+    fn discard_locals(&mut self, depth: usize) {
+        let ops: Vec<OpCode> = self
+            .compiler
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth.is_some_and(|d| d > depth))
+            .map(|local| match local.is_captured {
+                true => OpCode::CloseUpvalue,
+                false => OpCode::Pop,
+            })
+            .collect();
+        for op in ops {
+            self.emit_byte(op);
+        }
+    }
+    fn begin_loop(&mut self, start: usize) {
+        self.compiler.loops.push(Loop {
+            start,
+            scope_depth: self.compiler.scope_depth,
+            breaks: Vec::new(),
+        });
+    }
+    fn end_loop(&mut self) -> Result<()> {
+        let lp = self.compiler.loops.pop().expect("loop to end");
+        for jump in lp.breaks {
+            self.patch_jump(jump)?;
+        }
+        Ok(())
+    }
+    fn add_local(&mut self, name: Token) -> Result<()> {
+        if self.compiler.locals.len() == LOCALS_MAX {
+            return self.error("Too many local variables in scope.");
+        }
+        self.compiler.locals.push(Local {
+            name,
+            depth: None,
+            is_captured: false,
+            type_id: TypeId::Any,
+        });
+        Ok(())
+    }
+    fn declare_variable(&mut self) -> Result<()> {
+        if self.compiler.scope_depth == 0 {
+            return Ok(());
+        }
+        let name = self.previous;
+        if self
+            .compiler
+            .locals
+            .iter()
+            .any(|local| local.name.extract() == name.extract())
+        {
+            return self.error("Variable shadows an existing local variable.");
+        }
+        self.add_local(name)
+    }
+    fn mark_initialized(&mut self) {
+        if self.compiler.scope_depth == 0 {
+            return;
+        }
+        let depth = self.compiler.scope_depth;
+        if let Some(local) = self.compiler.locals.last_mut() {
+            local.depth = Some(depth);
+        }
     }
     fn define_variable(&mut self, global: u8) {
+        if self.compiler.scope_depth > 0 {
+            self.mark_initialized();
+            return;
+        }
         self.emit_bytes(OpCode::DefineGlobal, global);
     }
     fn named_variable(&mut self, name: Token, can_assign: bool) -> Result<()> {
+        let local = match self.compiler.resolve_local(name.extract()) {
+            Ok(local) => local,
+            Err(message) => return self.error(message),
+        };
+        let (get_op, set_op, arg) = if let Some(slot) = local {
+            (OpCode::GetLocal, OpCode::SetLocal, slot)
+        } else if let Some(index) = match self.compiler.resolve_upvalue(name.extract()) {
+            Ok(upvalue) => upvalue,
+            Err(message) => return self.error(message),
+        } {
+            (OpCode::GetUpvalue, OpCode::SetUpvalue, index)
+        } else {
+            let arg = self.identifier_constant(name)?;
+            (OpCode::GetGlobal, OpCode::SetGlobal, arg)
+        };
+        let id = self.type_of(name.extract());
+        let compound = compound_operator(self.current.id);
         let op = if can_assign && self.matches(TokenType::Equal) {
-            expression(self)?;
-            OpCode::SetGlobal
+            let start = self.current_chunk().code.len();
+            let value = expression(self)?;
+            let value = self.widen_literal(start, &id, value);
+            self.check_value(name, &id, &value)?;
+            set_op
+        } else if let (true, Some(op_type)) = (can_assign, compound) {
+            self.next();
+            let operator = self.previous;
+            self.emit_bytes(get_op, arg);
+            let value = expression(self)?;
+            emit_binary(self, operator, op_type, &id, &value)?;
+            let result = self.pop_type();
+            self.check_value(name, &id, &result)?;
+            set_op
         } else {
-            OpCode::GetGlobal
+            get_op
         };
-        let arg = self.identifier_constant(name);
         self.emit_bytes(op, arg);
+        self.push_type(id);
         Ok(())
     }
 }
 
-pub fn compile(source: &str) -> Result<Chunk> {
+pub fn compile(source: &str) -> Result<FunctionPointer> {
     let mut parser = Parser::new(source);
     // Prime the pump.
     parser.next();
     while !parser.matches(TokenType::EOF) {
         declaration(&mut parser)?;
+        if let Some(err) = parser.scanner_error.take() {
+            return Err(err);
+        }
+    }
+    let (ObjectPointer::Function(function), _) = parser.end_compiler() else {
+        unreachable!();
+    };
+    let mut vm = crate::vm::VM.lock();
+    for (key, id) in parser.global_types {
+        vm.memory.set_global_type(key, id);
     }
-    parser.end_compiler();
-    Ok(parser.chunk)
+    Ok(function)
 }
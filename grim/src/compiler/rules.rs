@@ -21,12 +21,16 @@ pub(super) enum Precedence {
     Equality,
     /// < > <= >=
     Comparison,
+    /// & | ^ << >>
+    Bitwise,
     /// + -
     Term,
-    /// * /
+    /// * / %
     Factor,
-    /// ! -
+    /// ! - ~
     Unary,
+    /// **
+    Power,
     /// . ()
     Call,
     Primary,
@@ -41,10 +45,12 @@ impl Precedence {
             Self::Or => Self::And,
             Self::And => Self::Equality,
             Self::Equality => Self::Comparison,
-            Self::Comparison => Self::Term,
+            Self::Comparison => Self::Bitwise,
+            Self::Bitwise => Self::Term,
             Self::Term => Self::Factor,
             Self::Factor => Self::Unary,
-            Self::Unary => Self::Call,
+            Self::Unary => Self::Power,
+            Self::Power => Self::Call,
             Self::Call => Self::Primary,
             Self::Primary => Self::Overflow,
             Self::Overflow => unreachable!(),
@@ -67,19 +73,25 @@ pub(super) fn get_rule(id: TokenType) -> &'static ParseRule {
 }
 
 #[rustfmt::skip]
-const RULES: [ParseRule; 43] = [
+const RULES: [ParseRule; 71] = [
     // Single character tokens
-    define!{LeftParen   , Some(grouping), None        , Precedence::None       },
+    define!{LeftParen   , Some(grouping), Some(call)  , Precedence::Call       },
     define!{RightParen  , None          , None        , Precedence::None       },
     define!{LeftBrace   , None          , None        , Precedence::None       },
     define!{RightBrace  , None          , None        , Precedence::None       },
     define!{RightBracket, None          , None        , Precedence::None       },
-    define!{LeftBracket , None          , None        , Precedence::None       },
+    define!{LeftBracket , Some(array)   , Some(index) , Precedence::Call       },
     define!{Plus        , None          , Some(binary), Precedence::Term       },
     define!{Star        , None          , Some(binary), Precedence::Factor     },
     define!{Slash       , None          , Some(binary), Precedence::Factor     },
     define!{Comma       , None          , None        , Precedence::None       },
     define!{Semicolon   , None          , None        , Precedence::None       },
+    define!{Question    , None          , None        , Precedence::None       },
+    define!{Percent     , None          , Some(binary), Precedence::Factor     },
+    define!{Amp         , None          , Some(binary), Precedence::Bitwise    },
+    define!{Pipe        , None          , Some(binary), Precedence::Bitwise    },
+    define!{Caret       , None          , Some(binary), Precedence::Bitwise    },
+    define!{Tilde       , Some(unary)   , None        , Precedence::None       },
     // One or more character tokens
     define!{Equal       , None          , None        , Precedence::None       },
     define!{EqualEqual  , None          , Some(binary), Precedence::Equality   },
@@ -89,17 +101,34 @@ const RULES: [ParseRule; 43] = [
     define!{GreaterEqual, None          , Some(binary), Precedence::Comparison },
     define!{Bang        , Some(unary)   , None        , Precedence::None       },
     define!{BangEqual   , None          , Some(binary), Precedence::Equality   },
-    define!{Dot         , None          , None        , Precedence::None       },
+    define!{Dot         , None          , Some(dot)   , Precedence::Call       },
     define!{DotDot      , None          , None        , Precedence::None       },
     define!{Minus       , Some(unary)   , Some(binary), Precedence::Term       },
     define!{MinusColon  , None          , None        , Precedence::None       },
-    define!{OrOr        , None          , None        , Precedence::None       },
-    define!{AndAnd      , None          , None        , Precedence::None       },
+    define!{OrOr        , None          , Some(or)    , Precedence::Or         },
+    define!{AndAnd      , None          , Some(and)   , Precedence::And        },
+    define!{StarStar    , None          , Some(binary), Precedence::Power      },
+    define!{LessLess    , None          , Some(binary), Precedence::Bitwise    },
+    define!{GreaterGreater, None        , Some(binary), Precedence::Bitwise    },
+    define!{FatArrow    , None          , None        , Precedence::None       },
+    // Compound assignment
+    define!{PlusEqual   , None          , None        , Precedence::None       },
+    define!{MinusEqual  , None          , None        , Precedence::None       },
+    define!{StarEqual   , None          , None        , Precedence::None       },
+    define!{SlashEqual  , None          , None        , Precedence::None       },
+    define!{PercentEqual, None          , None        , Precedence::None       },
+    define!{StarStarEqual, None         , None        , Precedence::None       },
+    define!{AmpEqual    , None          , None        , Precedence::None       },
+    define!{PipeEqual   , None          , None        , Precedence::None       },
+    define!{CaretEqual  , None          , None        , Precedence::None       },
+    define!{LessLessEqual, None         , None        , Precedence::None       },
+    define!{GreaterGreaterEqual, None   , None        , Precedence::None       },
     // Literals
     define!{Number      , Some(number)  , None        , Precedence::None       },
     define!{String      , Some(string)  , None        , Precedence::None       },
+    define!{Interpolation, Some(interpolation), None  , Precedence::None       },
     define!{Identifier  , Some(variable), None        , Precedence::None       },
-    define!{CharLit     , None          , None        , Precedence::None       },
+    define!{CharLit     , Some(char)    , None        , Precedence::None       },
     // Keywords
     define!{True        , Some(literal) , None        , Precedence::None       },
     define!{False       , Some(literal) , None        , Precedence::None       },
@@ -108,11 +137,17 @@ const RULES: [ParseRule; 43] = [
     define!{Char        , None          , None        , Precedence::None       },
     define!{Int         , None          , None        , Precedence::None       },
     define!{If          , None          , None        , Precedence::None       },
+    define!{Else        , None          , None        , Precedence::None       },
     define!{Nil         , Some(literal) , None        , Precedence::None       },
     define!{Typedef     , None          , None        , Precedence::None       },
     define!{Bind        , None          , None        , Precedence::None       },
     define!{Def         , None          , None        , Precedence::None       },
     define!{Print       , None          , None        , Precedence::None       },
     define!{Return      , None          , None        , Precedence::None       },
+    define!{While       , None          , None        , Precedence::None       },
+    define!{For         , None          , None        , Precedence::None       },
+    define!{Break       , None          , None        , Precedence::None       },
+    define!{Continue    , None          , None        , Precedence::None       },
+    define!{Match       , None          , None        , Precedence::None       },
     define!{EOF         , None          , None        , Precedence::None       },
 ];
@@ -1,5 +1,5 @@
 use crate::err::ScannerError;
-use std::{marker::PhantomData, result};
+use std::{marker::PhantomData, result, str::Chars};
 macro_rules! error {
     ( $line: expr, $message: tt, $( $value: expr ),* ) => {
         {
@@ -20,6 +20,8 @@ pub struct Scanner<'a> {
     tail: *const u8,
     at_end: bool,
     line: usize,
+    /// The count of unclosed '{' inside each `${` being scanned.
+    interpolations: Vec<usize>,
     _marker: PhantomData<&'a str>,
 }
 
@@ -31,6 +33,7 @@ impl Scanner<'_> {
             tail: unsafe { source.as_ptr().add(source.len()) },
             at_end: false,
             line: 1,
+            interpolations: Vec::new(),
             _marker: PhantomData,
         }
     }
@@ -50,7 +53,7 @@ impl Iterator for Scanner<'_> {
             return Some(Ok(self.make_token(TokenType::EOF)));
         };
         if char.is_ascii_digit() {
-            return Some(self.number());
+            return Some(self.number(char));
         }
         if is_alpha_numer(char) {
             return Some(self.identifier());
@@ -58,33 +61,74 @@ impl Iterator for Scanner<'_> {
         let id = match char {
             '(' => TokenType::LeftParen,
             ')' => TokenType::RightParen,
-            '{' => TokenType::LeftBrace,
-            '}' => TokenType::RightBrace,
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                TokenType::LeftBrace
+            }
+            // The '}' closing a `${` resumes the string around it.
+            '}' if self.interpolations.last() == Some(&0) => {
+                self.interpolations.pop();
+                return Some(self.string());
+            }
+            '}' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth -= 1;
+                }
+                TokenType::RightBrace
+            }
             '[' => TokenType::LeftBracket,
             ']' => TokenType::RightBracket,
+            '+' if self.matches('=') => TokenType::PlusEqual,
             '+' => TokenType::Plus,
+            '*' if self.matches('*') => match self.matches('=') {
+                true => TokenType::StarStarEqual,
+                false => TokenType::StarStar,
+            },
+            '*' if self.matches('=') => TokenType::StarEqual,
             '*' => TokenType::Star,
+            '/' if self.matches('=') => TokenType::SlashEqual,
             '/' => TokenType::Slash,
+            '%' if self.matches('=') => TokenType::PercentEqual,
+            '%' => TokenType::Percent,
             ',' => TokenType::Comma,
             ';' => TokenType::Semicolon,
+            '?' => TokenType::Question,
+            '~' => TokenType::Tilde,
+            '^' if self.matches('=') => TokenType::CaretEqual,
+            '^' => TokenType::Caret,
             '&' if self.peek() == Some('&') => {
                 self.advance();
                 TokenType::AndAnd
             }
+            '&' if self.matches('=') => TokenType::AmpEqual,
+            '&' => TokenType::Amp,
             '|' if self.peek() == Some('|') => {
                 self.advance();
                 TokenType::OrOr
             }
+            '|' if self.matches('=') => TokenType::PipeEqual,
+            '|' => TokenType::Pipe,
             '=' if self.peek() == Some('=') => {
                 self.advance();
                 TokenType::EqualEqual
             }
+            '=' if self.matches('>') => TokenType::FatArrow,
             '=' => TokenType::Equal,
+            '>' if self.matches('>') => match self.matches('=') {
+                true => TokenType::GreaterGreaterEqual,
+                false => TokenType::GreaterGreater,
+            },
             '>' if self.peek() == Some('=') => {
                 self.advance();
                 TokenType::GreaterEqual
             }
             '>' => TokenType::Greater,
+            '<' if self.matches('<') => match self.matches('=') {
+                true => TokenType::LessLessEqual,
+                false => TokenType::LessLess,
+            },
             '<' if self.peek() == Some('=') => {
                 self.advance();
                 TokenType::LessEqual
@@ -104,6 +148,7 @@ impl Iterator for Scanner<'_> {
                 self.advance();
                 TokenType::MinusColon
             }
+            '-' if self.matches('=') => TokenType::MinusEqual,
 
             '-' => TokenType::Minus,
             '"' => return Some(self.string()),
@@ -116,14 +161,59 @@ impl Iterator for Scanner<'_> {
 fn is_alpha_numer(c: char) -> bool {
     c.is_alphanumeric() || c == '_'
 }
+/// Decodes the escape sequences of a string or character literal.
+pub(super) fn unescape(literal: &str) -> result::Result<String, String> {
+    let mut out = String::with_capacity(literal.len());
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(c @ ('\\' | '\'' | '"' | '$')) => c,
+            Some('u') => unicode(&mut chars)?,
+            Some(c) => return Err(format!("invalid escape sequence '\\{}'.", c)),
+            None => return Err("unterminated escape sequence.".into()),
+        };
+        out.push(escaped);
+    }
+    Ok(out)
+}
+/// Decodes the `{..}` of a `\u{..}` escape, holding up to six hex digits.
+fn unicode(chars: &mut Chars) -> result::Result<char, String> {
+    let rest = chars.as_str();
+    let Some((digits, tail)) = rest.strip_prefix('{').and_then(|r| r.split_once('}')) else {
+        return Err("expect '{' and '}' around a unicode escape.".into());
+    };
+    *chars = tail.chars();
+    let valid = (1..=6).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_hexdigit());
+    valid
+        .then(|| u32::from_str_radix(digits, 16).ok())
+        .flatten()
+        .and_then(char::from_u32)
+        .ok_or_else(|| format!("invalid unicode escape '\\u{{{}}}'.", digits))
+}
 impl Scanner<'_> {
     fn advance(&mut self) -> Option<char> {
-        if self.is_at_end() {
-            return None;
+        let c = self.peek()?;
+        unsafe {
+            self.current = self.current.add(c.len_utf8());
         }
+        Some(c)
+    }
+
+    /// The source from `current` on. `current` only ever moves by whole
+    /// code points, so this is valid UTF-8.
+    fn rest(&self) -> &str {
         unsafe {
-            self.current = self.current.add(1);
-            Some(self.current.sub(1).read() as char)
+            let len = self.tail.offset_from(self.current) as usize;
+            let slice = std::slice::from_raw_parts(self.current, len);
+            std::str::from_utf8_unchecked(slice)
         }
     }
 
@@ -140,28 +230,39 @@ impl Scanner<'_> {
         }
     }
 
-    fn peek(&self) -> Option<char> {
-        if self.is_at_end() {
-            return None;
+    /// Consumes the next character if it is `expected`.
+    fn matches(&mut self, expected: char) -> bool {
+        if self.peek() != Some(expected) {
+            return false;
         }
+        self.advance();
+        true
+    }
 
-        unsafe { Some(self.current.read() as char) }
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
     }
 
     fn peek_next(&self) -> Option<char> {
-        if self.is_at_end() {
-            return None;
-        } else if unsafe { self.current.add(1) == self.tail } {
-            return None;
-        }
-
-        unsafe { Some(self.current.add(1).read() as char) }
+        self.rest().chars().nth(1)
     }
+    /// Scans a string literal up to its closing '"', or up to the next `${`
+    /// as an `Interpolation` segment.
     fn string(&mut self) -> Result<Token> {
         while !self.is_at_end() {
             if self.peek() == Some('"') {
                 break;
             }
+            if self.peek() == Some('$') && self.peek_next() == Some('{') {
+                self.advance();
+                self.advance();
+                self.interpolations.push(0);
+                let token = self.make_token(TokenType::Interpolation);
+                if let Err(message) = unescape(token.contents()) {
+                    return error!(self.line, "{}", message);
+                }
+                return Ok(token);
+            }
             if self.peek() == Some('\\') {
                 self.advance();
             }
@@ -171,31 +272,86 @@ impl Scanner<'_> {
                 }
             }
         }
-        // Consume the second '"'
-        self.advance();
         if self.is_at_end() {
             return error!(self.line, "unterminated string.");
         }
-        Ok(self.make_token(TokenType::String))
+        // Consume the second '"'
+        self.advance();
+        let token = self.make_token(TokenType::String);
+        if let Err(message) = unescape(token.contents()) {
+            return error!(self.line, "{}", message);
+        }
+        Ok(token)
     }
 
     fn char(&mut self) -> Result<Token> {
-        if self.peek_next() != Some('\'') {
+        while self.peek().is_some_and(|c| c != '\'' && c != '\n') {
+            // An escape sequence spans at least two characters.
+            if self.advance() == Some('\\') {
+                self.advance();
+            }
+        }
+        if self.peek() != Some('\'') {
             return error!(self.line, "unterminated character.");
         }
         self.advance();
-        self.advance();
-        Ok(self.make_token(TokenType::CharLit))
+        let token = self.make_token(TokenType::CharLit);
+        match unescape(token.contents()) {
+            Ok(c) if c.chars().count() == 1 => Ok(token),
+            Ok(_) => error!(
+                self.line,
+                "a character literal holds exactly one character."
+            ),
+            Err(message) => error!(self.line, "{}", message),
+        }
     }
 
-    fn number(&mut self) -> Result<Token> {
+    fn number(&mut self, first: char) -> Result<Token> {
+        if first == '0' && matches!(self.peek(), Some('x' | 'X' | 'b' | 'B')) {
+            self.advance();
+            // The compiler checks the digits against the radix.
+            while self
+                .peek()
+                .is_some_and(|c| c.is_ascii_hexdigit() || c == '_')
+            {
+                self.advance();
+            }
+            self.suffix();
+            return Ok(self.make_token(TokenType::Number));
+        }
+        self.digits();
+        // A '.' only starts a fraction when a digit follows, so that
+        // ranges like `1..5` still scan.
+        if self.peek() == Some('.') && self.peek_next().is_some_and(|c| c.is_ascii_digit()) {
+            self.advance();
+            self.digits();
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            self.advance();
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.advance();
+            }
+            if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                return error!(self.line, "expect digits after exponent.");
+            }
+            self.digits();
+        }
+        self.suffix();
+        Ok(self.make_token(TokenType::Number))
+    }
+    fn digits(&mut self) {
         while let Some(n) = self.peek() {
-            if !n.is_ascii_digit() {
+            if !n.is_ascii_digit() && n != '_' {
                 break;
             }
             self.advance();
         }
-        Ok(self.make_token(TokenType::Number))
+    }
+    /// Consumes a suffix such as `i64` glued to a number.
+    fn suffix(&mut self) {
+        while self.peek().is_some_and(is_alpha_numer) {
+            self.advance();
+        }
     }
     fn skip_whitespace(&mut self) {
         loop {
@@ -233,6 +389,10 @@ impl Scanner<'_> {
         }
     }
     fn check_identifier(&self, start: usize, len: usize, rest: &str, id: TokenType) -> TokenType {
+        let token_len = unsafe { self.current.offset_from(self.start) as usize };
+        if token_len != start + len {
+            return TokenType::Identifier;
+        }
         let slice = unsafe { std::slice::from_raw_parts(self.start.add(start), len) };
         let str = unsafe { std::str::from_utf8_unchecked(slice) };
         if str == rest {
@@ -242,26 +402,51 @@ impl Scanner<'_> {
         }
     }
     fn id_type(&self) -> TokenType {
+        let token_len = unsafe { self.current.offset_from(self.start) as usize };
+        // A one letter identifier has no second byte to read, and '\0'
+        // starts no keyword.
+        let second = match token_len {
+            1 => '\0',
+            _ => unsafe { self.start.add(1).read() as char },
+        };
         let (start, len, rest, id) = match unsafe { self.start.read() as char } {
-            'b' => (1, 3, "ind", TokenType::Bind),
-            'c' => (1, 3, "har", TokenType::Char),
+            'b' => match second {
+                'i' => (2, 2, "nd", TokenType::Bind),
+                'r' => (2, 3, "eak", TokenType::Break),
+                _ => return TokenType::Identifier,
+            },
+            'c' => match second {
+                'h' => (2, 2, "ar", TokenType::Char),
+                'o' => (2, 6, "ntinue", TokenType::Continue),
+                _ => return TokenType::Identifier,
+            },
             'd' => (1, 2, "ef", TokenType::Def),
-            'e' => (1, 3, "num", TokenType::Enum),
-            'f' => (1, 4, "alse", TokenType::False),
-            'i' => match unsafe { self.start.add(1).read() as char } {
+            'e' => match second {
+                'l' => (2, 2, "se", TokenType::Else),
+                'n' => (2, 2, "um", TokenType::Enum),
+                _ => return TokenType::Identifier,
+            },
+            'f' => match second {
+                'a' => (2, 3, "lse", TokenType::False),
+                'o' => (2, 1, "r", TokenType::For),
+                _ => return TokenType::Identifier,
+            },
+            'i' => match second {
                 'n' => (2, 1, "t", TokenType::Int),
                 'f' => (1, 1, "f", TokenType::If),
                 _ => return TokenType::Identifier,
             },
+            'm' => (1, 4, "atch", TokenType::Match),
             'n' => (1, 2, "il", TokenType::Nil),
             'p' => (1, 4, "rint", TokenType::Print),
-            't' => match unsafe { self.start.add(1).read() as char } {
+            't' => match second {
                 'r' => (2, 2, "ue", TokenType::True),
                 'y' => (2, 5, "pedef", TokenType::Typedef),
                 _ => return TokenType::Identifier,
             },
             'r' => (1, 5, "eturn", TokenType::Return),
-            's' => (1, 4, "ruct", TokenType::Struct),
+            's' => (1, 5, "truct", TokenType::Struct),
+            'w' => (1, 4, "hile", TokenType::While),
 
             // struct
             _ => return TokenType::Identifier,
@@ -269,7 +454,7 @@ impl Scanner<'_> {
         self.check_identifier(start, len, rest, id)
     }
     fn identifier(&mut self) -> Result<Token> {
-        while is_alpha_numer(self.peek().unwrap()) || self.peek().unwrap().is_ascii_digit() {
+        while self.peek().is_some_and(is_alpha_numer) {
             self.advance();
         }
         let id = self.id_type();
@@ -291,11 +476,22 @@ impl Token {
             std::str::from_utf8_unchecked(slice)
         }
     }
+    /// The text of a string or character literal between its quotes. A
+    /// string segment runs from a '"' or '}' up to a `${` or '"'.
+    pub fn contents(&self) -> &str {
+        let literal = self.extract();
+        let end = match self.id {
+            TokenType::Interpolation => 2,
+            _ => 1,
+        };
+        &literal[1..literal.len() - end]
+    }
 }
 impl Default for Token {
     fn default() -> Self {
         Self {
-            start: std::ptr::null(),
+            // Dangling rather than null so `extract` yields an empty str.
+            start: std::ptr::NonNull::dangling().as_ptr(),
             id: TokenType::default(),
             len: usize::default(),
             line: usize::default(),
@@ -317,6 +513,12 @@ pub enum TokenType {
     Slash,
     Comma,
     Semicolon,
+    Question,
+    Percent,
+    Amp,
+    Pipe,
+    Caret,
+    Tilde,
     // One or more character tokens
     Equal,
     EqualEqual,
@@ -332,9 +534,27 @@ pub enum TokenType {
     MinusColon,
     OrOr,
     AndAnd,
+    StarStar,
+    LessLess,
+    GreaterGreater,
+    FatArrow,
+    // Compound assignment
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    StarStarEqual,
+    AmpEqual,
+    PipeEqual,
+    CaretEqual,
+    LessLessEqual,
+    GreaterGreaterEqual,
     // Literals
     Number,
     String,
+    /// A string segment followed by `${`.
+    Interpolation,
     Identifier,
     CharLit,
     // Keywords
@@ -345,12 +565,18 @@ pub enum TokenType {
     Char,
     Int,
     If,
+    Else,
     Nil,
     Typedef,
     Bind,
     Def,
     Print,
     Return,
+    While,
+    For,
+    Break,
+    Continue,
+    Match,
     #[default]
     EOF,
 }
@@ -0,0 +1,164 @@
+use super::{scanner::TokenType, Parser, Result, Token};
+use crate::{
+    allocate_string,
+    lang_core::{objects::Pointable, prelude::*, types::string_method},
+};
+
+/// The static type of `left op right`, or `None` if the operator doesn't
+/// apply to those operands.
+pub(super) fn binary_type(op: TokenType, left: &TypeId, right: &TypeId) -> Option<TypeId> {
+    use TypeId::{Any, Bool, Char, Float, Long, Number, String};
+    let arithmetic = matches!(
+        op,
+        TokenType::Plus
+            | TokenType::Minus
+            | TokenType::Star
+            | TokenType::Slash
+            | TokenType::Percent
+            | TokenType::StarStar
+    );
+    let bitwise = matches!(
+        op,
+        TokenType::Amp
+            | TokenType::Pipe
+            | TokenType::Caret
+            | TokenType::LessLess
+            | TokenType::GreaterGreater
+    );
+    let comparison = matches!(
+        op,
+        TokenType::Less | TokenType::LessEqual | TokenType::Greater | TokenType::GreaterEqual
+    );
+    match (op, left, right) {
+        (TokenType::EqualEqual | TokenType::BangEqual, _, _) => Some(Bool),
+        (_, Any, _) | (_, _, Any) if comparison => Some(Bool),
+        (_, Any, _) | (_, _, Any) => Some(Any),
+        (_, Number | Long | Float, Number | Long | Float) | (_, Char, Char) if comparison => {
+            Some(Bool)
+        }
+        (_, Float, Number | Long | Float) | (_, Number | Long, Float) if arithmetic => Some(Float),
+        (_, Long, Number | Long) | (_, Number, Long) if arithmetic || bitwise => Some(Long),
+        (TokenType::Plus, String, String) => Some(String),
+        (TokenType::Plus, Char, Number) => Some(Char),
+        (TokenType::Minus, Char, Char) => Some(Number),
+        (TokenType::Minus, Char, Number) => Some(Char),
+        (_, Number, Number) if arithmetic || bitwise => Some(Number),
+        _ => None,
+    }
+}
+
+/// The parameter types of `callee`, empty if they are not known.
+pub(super) fn param_types(callee: &TypeId) -> Vec<TypeId> {
+    match callee {
+        TypeId::Fn(params, _) => params.clone(),
+        TypeId::Typedef(ObjectPointer::Struct(s)) => {
+            let def = s.get_ref().expect("valid struct");
+            def.fields.iter().map(|(_, id)| id.clone()).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The static type of calling a `callee` with `args`.
+pub(super) fn call_type(
+    parser: &Parser,
+    token: Token,
+    callee: &TypeId,
+    args: &[TypeId],
+) -> Result<TypeId> {
+    let (params, returns) = match callee {
+        TypeId::Fn(_, returns) => (param_types(callee), (**returns).clone()),
+        TypeId::Typedef(ObjectPointer::Struct(s)) => {
+            let name = s.get_ref().expect("valid struct").name;
+            (param_types(callee), TypeId::Custom(name))
+        }
+        TypeId::Any | TypeId::Function => return Ok(TypeId::Any),
+        _ => return parser.error_at(token, "Can only call functions and structs."),
+    };
+    if params.len() != args.len() {
+        let message = format!(
+            "Expected {} arguments but got {}.",
+            params.len(),
+            args.len()
+        );
+        return parser.error_at(token, &message);
+    }
+    for (param, arg) in params.iter().zip(args) {
+        parser.check_type(token, param, arg)?;
+    }
+    Ok(returns)
+}
+
+/// The static type of the field or enum variant `name` of `receiver`.
+pub(super) fn field_type(parser: &Parser, name: Token, receiver: &TypeId) -> Result<TypeId> {
+    let field = allocate_string!(name.extract());
+    match receiver {
+        TypeId::Any => Ok(TypeId::Any),
+        TypeId::Custom(type_name) => {
+            let type_name = type_name.get_ref().expect("valid string");
+            match parser.type_of(type_name) {
+                TypeId::Typedef(ObjectPointer::Struct(s)) => {
+                    let def = s.get_ref().expect("valid struct");
+                    match def.field(field) {
+                        Some(index) => Ok(def.fields[index].1.clone()),
+                        None => {
+                            let message = format!("Undefined field '{}' on {}.", field, def.name);
+                            parser.error_at(name, &message)
+                        }
+                    }
+                }
+                TypeId::Typedef(ObjectPointer::Enum(_)) => {
+                    parser.error_at(name, "Only struct instances have fields.")
+                }
+                // The declaration is out of sight, leave it to the vm.
+                _ => Ok(TypeId::Any),
+            }
+        }
+        TypeId::Typedef(ObjectPointer::Enum(e)) => {
+            let def = e.get_ref().expect("valid enum");
+            if def.discriminant(field).is_none() {
+                let message = format!("Undefined variant '{}' on {}.", field, def.name);
+                return parser.error_at(name, &message);
+            }
+            Ok(TypeId::Custom(def.name))
+        }
+        _ => parser.error_at(name, "Only struct instances have fields."),
+    }
+}
+
+/// The static type of calling the method `name` of `receiver` with `args`.
+pub(super) fn method_type(
+    parser: &Parser,
+    name: Token,
+    receiver: &TypeId,
+    args: &[TypeId],
+) -> Result<TypeId> {
+    match receiver {
+        TypeId::Array(..) => match name.extract() {
+            "len" if args.is_empty() => Ok(TypeId::Number),
+            "len" => {
+                let message = format!("Expected 0 arguments but got {}.", args.len());
+                parser.error_at(name, &message)
+            }
+            method => {
+                let message = format!("Undefined method '{}' on array.", method);
+                parser.error_at(name, &message)
+            }
+        },
+        TypeId::String => match string_method(name.extract()) {
+            Some((params, returns)) => {
+                call_type(parser, name, &TypeId::Fn(params, Box::new(returns)), args)
+            }
+            None => {
+                let message = format!("Undefined method '{}' on String.", name.extract());
+                parser.error_at(name, &message)
+            }
+        },
+        TypeId::Custom(_) => {
+            let callee = field_type(parser, name, receiver)?;
+            call_type(parser, name, &callee, args)
+        }
+        TypeId::Any => Ok(TypeId::Any),
+        _ => parser.error_at(name, "Only instances, arrays and strings have methods."),
+    }
+}
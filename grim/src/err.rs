@@ -36,7 +36,7 @@ impl Display for ScannerError {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CompilerError(String, usize);
 
 impl CompilerError {
@@ -64,6 +64,10 @@ impl VmError {
     pub fn new<T>(message: String) -> Result<T, Self> {
         Err(Self(message, 70))
     }
+    /// Prefixes the message with the source line the error happened on.
+    pub fn at_line(self, line: u32) -> Self {
+        Self(format!("[line {}] {}", line, self.0), self.1)
+    }
 }
 impl std::error::Error for VmError {}
 impl Display for VmError {
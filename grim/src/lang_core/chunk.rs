@@ -1,4 +1,4 @@
-use super::Type;
+use super::{types::TypeId, Type};
 use crate::vm::Ip;
 use std::fmt::Display;
 #[derive(Default, Debug)]
@@ -6,6 +6,8 @@ pub struct Chunk {
     pub code: Vec<u8>,
     pub lines: Line,
     pub constants: Vec<Type>,
+    /// The types `CheckType` instructions check values against.
+    pub types: Vec<TypeId>,
 }
 
 impl Chunk {
@@ -18,19 +20,29 @@ impl Chunk {
         self.lines.push(line);
     }
 
-    pub fn constant<T: Into<Type>>(&mut self, value: T) -> u8 {
+    /// Adds `value` to the constants and returns its index, or `None` if
+    /// the one byte instructions address them with is exhausted.
+    pub fn constant<T: Into<Type>>(&mut self, value: T) -> Option<u8> {
+        let index = u8::try_from(self.constants.len()).ok()?;
         self.constants.push(value.into());
-        (self.constants.len() - 1) as u8
+        Some(index)
+    }
+
+    /// Like [`Chunk::constant`] for the types `CheckType` checks against.
+    pub fn type_constant(&mut self, id: TypeId) -> Option<u8> {
+        let index = u8::try_from(self.types.len()).ok()?;
+        self.types.push(id);
+        Some(index)
     }
 }
 
 impl Display for Chunk {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut out = format!("== test ==\n");
+        let mut out = String::new();
         let mut ip = Ip::from(self);
         let mut pos = 0;
         loop {
-            out.push_str(&format!("{:04} {:04} ", pos, ip.line(pos as u8)));
+            out.push_str(&format!("{:04} {:04} ", pos, ip.line(pos)));
             let (count, string) = ip.dissasemble_instruction();
             out.push_str(&string);
             out.push('\n');
@@ -45,6 +57,7 @@ impl Display for Chunk {
     }
 }
 
+/// Source lines of a chunk's bytes, run-length encoded as (line, count).
 #[derive(Default, Debug)]
 pub struct Line {
     pub lines: Vec<(u32, u32)>,
@@ -52,23 +65,20 @@ pub struct Line {
 
 impl Line {
     pub fn push(&mut self, line: u32) {
-        for (l, c) in &mut self.lines {
-            if *l == line {
-                *c += 1;
-                return;
-            }
+        match self.lines.last_mut() {
+            Some((l, c)) if *l == line => *c += 1,
+            _ => self.lines.push((line, 1)),
         }
-        self.lines.push((line, 1));
     }
 
-    pub fn get_line(&self, loc: u8) -> u32 {
-        let mut loc = loc as u32;
+    pub fn get_line(&self, loc: usize) -> u32 {
+        let mut loc = loc;
         for (l, c) in &self.lines {
-            if loc < *c {
+            let c = *c as usize;
+            if loc < c {
                 return *l;
-            } else {
-                loc -= c;
             }
+            loc -= c;
         }
         0
     }
@@ -103,4 +113,10 @@ op_code! { Return, 0, Constant, 1, Add, 2, Subtract, 3,
 Divide, 4, Multiply, 5, Negate, 6, Nil, 7, True, 8,
 False, 9, Not, 10, Equal, 11, Greater, 12, Less, 13,
 Print, 90, Pop, 14, DefineGlobal, 15, GetGlobal, 16,
-SetGlobal, 17 }
+SetGlobal, 17, GetLocal, 18, SetLocal, 19, Jump, 20, JumpIfFalse, 21,
+Loop, 22, Call, 23, Closure, 24, GetUpvalue, 25,
+SetUpvalue, 26, CloseUpvalue, 27, GetField, 28, SetField, 29,
+Invoke, 30, Array, 31, Range, 32, GetIndex, 33, SetIndex, 34,
+Modulo, 35, Power, 36, BitAnd, 37, BitOr, 38, BitXor, 39, BitNot, 40,
+ShiftLeft, 41, ShiftRight, 42, NotEqual, 43, GreaterEqual, 44,
+LessEqual, 45, BuildString, 46, CheckType, 47 }
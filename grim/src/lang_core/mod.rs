@@ -1,3 +1,4 @@
+use objects::Pointable;
 use std::{fmt::Display, result};
 pub mod chunk;
 pub mod objects;
@@ -7,7 +8,13 @@ pub mod prelude {
     pub use super::{
         super::err::TryFromValueError,
         chunk::{Chunk, OpCode},
-        objects::{ObjString, Object, ObjectPointer, StringPointer},
+        objects::{
+            ClosurePointer, EnumPointer, FunctionPointer, NativeFn, NativePointer, ObjArray,
+            ObjClosure, ObjFunction, ObjInstance, ObjNative, ObjString, ObjStruct, ObjUpvalue,
+            Object, ObjectPointer, StringPointer, StructPointer, UpvalueLocation, UpvaluePointer,
+            RANGE_MAX,
+        },
+        types::TypeId,
         Number, Result as ValResult, Type,
     };
 }
@@ -15,23 +22,17 @@ pub mod prelude {
 use prelude::*;
 pub type Result<T> = result::Result<T, TryFromValueError>;
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
-pub enum TypeId {
-    Number,
-    Bool,
-    String,
-    Module,
-    #[default]
-    Nil,
-    Custom(StringPointer),
-}
-
 pub type Number = i32;
-#[derive(Default, PartialEq, PartialOrd, Eq, Debug, Clone, Copy)]
+#[derive(Default, PartialEq, PartialOrd, Debug, Clone, Copy)]
 pub enum Type {
     Number(Number),
+    Long(i64),
+    Float(f64),
     Bool(bool),
+    Char(char),
     Object(ObjectPointer),
+    /// A variant of a `typedef enum`, identified by its discriminant.
+    Enum(EnumPointer, Number),
     #[default]
     Nil,
 }
@@ -41,18 +42,11 @@ impl Type {
             Self::Nil => true,
             Self::Bool(b) => !b,
             Self::Number(_) => false,
+            Self::Long(_) => false,
+            Self::Float(_) => false,
+            Self::Char(_) => false,
             Self::Object(_) => false,
-        }
-    }
-    pub fn types_equal(&self, other: &Type) -> bool {
-        match (self, other) {
-            (Type::Bool(_), Type::Bool(_))
-            | (Type::Number(_), Type::Number(_))
-            | (Type::Nil, _) => true,
-            (Type::Object(old_ptr), Type::Object(new)) => match (old_ptr, new) {
-                (ObjectPointer::String(_), ObjectPointer::String(_)) => true,
-            },
-            _ => false,
+            Self::Enum(..) => false,
         }
     }
 }
@@ -64,8 +58,19 @@ impl Display for Type {
             match self {
                 Self::Bool(b) => b.to_string(),
                 Self::Number(n) => n.to_string(),
+                Self::Long(n) => n.to_string(),
+                // Debug keeps the `.0` that tells a float from an int.
+                Self::Float(n) => format!("{:?}", n),
+                Self::Char(c) => c.to_string(),
                 Self::Nil => "nil".to_string(),
                 Self::Object(o) => format!("{}", o),
+                Self::Enum(e, value) => {
+                    let e = e.get_ref().expect("valid enum");
+                    match e.variant_name(*value) {
+                        Some(name) => name.to_string(),
+                        None => format!("{}({})", e.name, value),
+                    }
+                }
             }
         )
     }
@@ -77,12 +82,30 @@ impl From<i32> for Type {
     }
 }
 
+impl From<i64> for Type {
+    fn from(n: i64) -> Self {
+        Self::Long(n)
+    }
+}
+
+impl From<f64> for Type {
+    fn from(n: f64) -> Self {
+        Self::Float(n)
+    }
+}
+
 impl From<bool> for Type {
     fn from(b: bool) -> Self {
         Self::Bool(b)
     }
 }
 
+impl From<char> for Type {
+    fn from(c: char) -> Self {
+        Self::Char(c)
+    }
+}
+
 impl TryFrom<Type> for i32 {
     type Error = TryFromValueError;
     fn try_from(value: Type) -> result::Result<Self, Self::Error> {
@@ -90,8 +113,46 @@ impl TryFrom<Type> for i32 {
         match value {
             Type::Number(n) => Ok(n),
             Type::Nil => error("nil"),
+            Type::Long(_) => error("i64"),
+            Type::Float(_) => error("float"),
+            Type::Bool(_) => error("bool"),
+            Type::Char(_) => error("char"),
+            Type::Object(_) => error("object"),
+            Type::Enum(..) => error("enum"),
+        }
+    }
+}
+
+impl TryFrom<Type> for i64 {
+    type Error = TryFromValueError;
+    fn try_from(value: Type) -> result::Result<Self, Self::Error> {
+        let error = |got: &str| TryFromValueError::new("i64", got);
+        match value {
+            Type::Long(n) => Ok(n),
+            Type::Number(n) => Ok(n as i64),
+            Type::Nil => error("nil"),
+            Type::Float(_) => error("float"),
+            Type::Bool(_) => error("bool"),
+            Type::Char(_) => error("char"),
+            Type::Object(_) => error("object"),
+            Type::Enum(..) => error("enum"),
+        }
+    }
+}
+
+impl TryFrom<Type> for f64 {
+    type Error = TryFromValueError;
+    fn try_from(value: Type) -> result::Result<Self, Self::Error> {
+        let error = |got: &str| TryFromValueError::new("float", got);
+        match value {
+            Type::Float(n) => Ok(n),
+            Type::Number(n) => Ok(n as f64),
+            Type::Long(n) => Ok(n as f64),
+            Type::Nil => error("nil"),
             Type::Bool(_) => error("bool"),
+            Type::Char(_) => error("char"),
             Type::Object(_) => error("object"),
+            Type::Enum(..) => error("enum"),
         }
     }
 }
@@ -104,7 +165,28 @@ impl TryFrom<Type> for bool {
             Type::Bool(b) => Ok(b),
             Type::Nil => error("nil"),
             Type::Number(_) => error("number"),
+            Type::Long(_) => error("i64"),
+            Type::Float(_) => error("float"),
+            Type::Char(_) => error("char"),
+            Type::Object(_) => error("object"),
+            Type::Enum(..) => error("enum"),
+        }
+    }
+}
+
+impl TryFrom<Type> for char {
+    type Error = TryFromValueError;
+    fn try_from(value: Type) -> result::Result<Self, Self::Error> {
+        let error = |got: &str| TryFromValueError::new("char", got);
+        match value {
+            Type::Char(c) => Ok(c),
+            Type::Nil => error("nil"),
+            Type::Number(_) => error("number"),
+            Type::Long(_) => error("i64"),
+            Type::Float(_) => error("float"),
+            Type::Bool(_) => error("bool"),
             Type::Object(_) => error("object"),
+            Type::Enum(..) => error("enum"),
         }
     }
 }
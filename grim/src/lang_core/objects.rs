@@ -1,13 +1,28 @@
-use crate::lang_core::Type;
-use std::fmt::{self, Display};
+use crate::{
+    err::VmError,
+    lang_core::{chunk::Chunk, types::TypeId, Number, Type},
+    vm::Vm,
+};
+use std::{
+    cell::{Cell, RefCell},
+    fmt::{self, Display},
+};
 pub trait Pointable {
     type Obj;
     fn get_ref(self) -> Option<&'static Self::Obj>;
     fn as_raw(self) -> *const Self::Obj;
 }
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Eq)]
 pub enum ObjectPointer {
     String(StringPointer),
+    Function(FunctionPointer),
+    Closure(ClosurePointer),
+    Upvalue(UpvaluePointer),
+    Struct(StructPointer),
+    Instance(InstancePointer),
+    Enum(EnumPointer),
+    Array(ArrayPointer),
+    Native(NativePointer),
 }
 
 impl Display for ObjectPointer {
@@ -17,20 +32,49 @@ impl Display for ObjectPointer {
             "{}",
             match self {
                 ObjectPointer::String(s) => format!("{}", s),
+                ObjectPointer::Function(fun) => format!("{}", fun),
+                ObjectPointer::Closure(c) => format!("{}", c),
+                ObjectPointer::Upvalue(u) => format!("{}", u),
+                ObjectPointer::Struct(s) => format!("{}", s),
+                ObjectPointer::Instance(i) => format!("{}", i),
+                ObjectPointer::Enum(e) => format!("{}", e),
+                ObjectPointer::Array(a) => format!("{}", a),
+                ObjectPointer::Native(n) => format!("{}", n),
             },
         )
     }
 }
-impl From<&'static Object> for ObjectPointer {
-    fn from(o: &'static Object) -> Self {
+impl From<ObjectPointer> for Type {
+    fn from(o: ObjectPointer) -> Self {
+        Type::Object(o)
+    }
+}
+impl From<&Object> for ObjectPointer {
+    fn from(o: &Object) -> Self {
         match o {
-            Object::String(s) => ObjectPointer::String(StringPointer::from(s)),
+            Object::String(s) => ObjectPointer::String(StringPointer::new(s)),
+            Object::Function(f) => ObjectPointer::Function(FunctionPointer::new(f)),
+            Object::Closure(c) => ObjectPointer::Closure(ClosurePointer::new(c)),
+            Object::Upvalue(u) => ObjectPointer::Upvalue(UpvaluePointer::new(u)),
+            Object::Struct(s) => ObjectPointer::Struct(StructPointer::new(s)),
+            Object::Instance(i) => ObjectPointer::Instance(InstancePointer::new(i)),
+            Object::Enum(e) => ObjectPointer::Enum(EnumPointer::new(e)),
+            Object::Array(a) => ObjectPointer::Array(ArrayPointer::new(a)),
+            Object::Native(n) => ObjectPointer::Native(NativePointer::new(n)),
         }
     }
 }
 #[derive(Debug)]
 pub enum Object {
     String(ObjString),
+    Function(ObjFunction),
+    Closure(ObjClosure),
+    Upvalue(ObjUpvalue),
+    Struct(ObjStruct),
+    Instance(ObjInstance),
+    Enum(ObjEnum),
+    Array(ObjArray),
+    Native(ObjNative),
 }
 unsafe impl Send for Object {}
 unsafe impl Sync for Object {}
@@ -38,13 +82,21 @@ impl Display for Object {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::String(s) => write!(f, "{}", s),
+            Self::Function(fun) => write!(f, "{}", fun),
+            Self::Closure(c) => write!(f, "{}", c),
+            Self::Upvalue(u) => write!(f, "{}", u),
+            Self::Struct(s) => write!(f, "{}", s),
+            Self::Instance(i) => write!(f, "{}", i),
+            Self::Enum(e) => write!(f, "{}", e),
+            Self::Array(a) => write!(f, "{}", a),
+            Self::Native(n) => write!(f, "{}", n),
         }
     }
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, PartialOrd, Eq)]
 pub struct ObjString {
-    chars: Vec<u8>,
+    chars: String,
 }
 
 unsafe impl Send for ObjString {}
@@ -52,7 +104,7 @@ unsafe impl Sync for ObjString {}
 impl ObjString {
     pub fn new(message: &str) -> Self {
         Self {
-            chars: message.chars().map(|x| x as u8).collect(),
+            chars: message.to_string(),
             ..Default::default()
         }
     }
@@ -72,12 +124,7 @@ impl From<ObjString> for Object {
 }
 impl Display for ObjString {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let string = if self.chars[0] as char == '"' {
-            unsafe { std::str::from_utf8_unchecked(&self.chars[1..self.len() - 1]) }
-        } else {
-            unsafe { std::str::from_utf8_unchecked(&self.chars[..]) }
-        };
-        write!(f, "{}", string)
+        write!(f, "{}", self.chars)
     }
 }
 
@@ -89,14 +136,14 @@ impl From<StringPointer> for Type {
 impl Default for ObjString {
     fn default() -> Self {
         Self {
-            chars: Vec::default(),
+            chars: String::default(),
         }
     }
 }
 impl std::ops::Deref for ObjString {
     type Target = str;
     fn deref(&self) -> &Self::Target {
-        unsafe { std::str::from_utf8_unchecked(&self.chars[..]) }
+        &self.chars
     }
 }
 
@@ -135,3 +182,333 @@ impl From<StringPointer> for ObjectPointer {
         Self::String(s)
     }
 }
+
+#[derive(Debug, Default)]
+pub struct ObjFunction {
+    pub arity: u8,
+    pub upvalue_count: u8,
+    pub chunk: Chunk,
+    /// `None` for the top level script.
+    pub name: Option<StringPointer>,
+}
+unsafe impl Send for ObjFunction {}
+unsafe impl Sync for ObjFunction {}
+impl ObjFunction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+impl From<ObjFunction> for Object {
+    fn from(f: ObjFunction) -> Self {
+        Self::Function(f)
+    }
+}
+impl Display for ObjFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "<fn {}>", name),
+            None => write!(f, "<script>"),
+        }
+    }
+}
+
+/// Defines a typed pointer to an object living in the vm's memory along with
+/// its conversions into [`ObjectPointer`] and [`Type`].
+macro_rules! object_pointer {
+    ($name: ident, $obj: ty, $variant: ident) => {
+        #[derive(Debug, Clone, Copy, Hash, PartialEq, PartialOrd, Eq)]
+        pub struct $name(*const $obj);
+        impl $name {
+            pub fn new(ptr: *const $obj) -> Self {
+                Self(ptr)
+            }
+        }
+        unsafe impl Send for $name {}
+        unsafe impl Sync for $name {}
+        impl Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let obj = unsafe { self.0.as_ref().expect("valid pointer") };
+                write!(f, "{}", obj)
+            }
+        }
+        impl Pointable for $name {
+            type Obj = $obj;
+
+            fn get_ref(self) -> Option<&'static Self::Obj> {
+                unsafe { self.0.as_ref() }
+            }
+            fn as_raw(self) -> *const Self::Obj {
+                self.0
+            }
+        }
+        impl From<$name> for ObjectPointer {
+            fn from(p: $name) -> Self {
+                Self::$variant(p)
+            }
+        }
+        impl From<$name> for Type {
+            fn from(p: $name) -> Self {
+                Type::Object(ObjectPointer::$variant(p))
+            }
+        }
+    };
+}
+object_pointer!(FunctionPointer, ObjFunction, Function);
+object_pointer!(ClosurePointer, ObjClosure, Closure);
+object_pointer!(UpvaluePointer, ObjUpvalue, Upvalue);
+object_pointer!(StructPointer, ObjStruct, Struct);
+object_pointer!(InstancePointer, ObjInstance, Instance);
+object_pointer!(EnumPointer, ObjEnum, Enum);
+object_pointer!(ArrayPointer, ObjArray, Array);
+object_pointer!(NativePointer, ObjNative, Native);
+
+#[derive(Debug)]
+pub struct ObjClosure {
+    pub function: FunctionPointer,
+    pub upvalues: Vec<UpvaluePointer>,
+}
+unsafe impl Send for ObjClosure {}
+unsafe impl Sync for ObjClosure {}
+impl ObjClosure {
+    pub fn new(function: FunctionPointer, upvalues: Vec<UpvaluePointer>) -> Self {
+        Self { function, upvalues }
+    }
+}
+impl From<ObjClosure> for Object {
+    fn from(c: ObjClosure) -> Self {
+        Self::Closure(c)
+    }
+}
+impl Display for ObjClosure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.function)
+    }
+}
+
+/// Where the variable captured by an upvalue currently lives.
+#[derive(Debug, Clone, Copy)]
+pub enum UpvalueLocation {
+    /// Still on the vm stack at the given slot.
+    Open(usize),
+    /// Moved off the stack once its scope ended.
+    Closed(Type),
+}
+
+#[derive(Debug)]
+pub struct ObjUpvalue {
+    pub location: Cell<UpvalueLocation>,
+}
+unsafe impl Send for ObjUpvalue {}
+unsafe impl Sync for ObjUpvalue {}
+impl ObjUpvalue {
+    pub fn new(slot: usize) -> Self {
+        Self {
+            location: Cell::new(UpvalueLocation::Open(slot)),
+        }
+    }
+}
+impl From<ObjUpvalue> for Object {
+    fn from(u: ObjUpvalue) -> Self {
+        Self::Upvalue(u)
+    }
+}
+impl Display for ObjUpvalue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "upvalue")
+    }
+}
+
+/// A `typedef struct` declaration.
+#[derive(Debug)]
+pub struct ObjStruct {
+    pub name: StringPointer,
+    pub fields: Vec<(StringPointer, TypeId)>,
+}
+unsafe impl Send for ObjStruct {}
+unsafe impl Sync for ObjStruct {}
+impl ObjStruct {
+    pub fn new(name: StringPointer, fields: Vec<(StringPointer, TypeId)>) -> Self {
+        Self { name, fields }
+    }
+    /// The position of the field called `name`.
+    pub fn field(&self, name: StringPointer) -> Option<usize> {
+        self.fields.iter().position(|(field, _)| *field == name)
+    }
+}
+impl From<ObjStruct> for Object {
+    fn from(s: ObjStruct) -> Self {
+        Self::Struct(s)
+    }
+}
+impl Display for ObjStruct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<struct {}>", self.name)
+    }
+}
+
+#[derive(Debug)]
+pub struct ObjInstance {
+    pub typedef: StructPointer,
+    /// Field values in declaration order.
+    pub fields: RefCell<Vec<Type>>,
+}
+unsafe impl Send for ObjInstance {}
+unsafe impl Sync for ObjInstance {}
+impl ObjInstance {
+    pub fn new(typedef: StructPointer, fields: Vec<Type>) -> Self {
+        Self {
+            typedef,
+            fields: RefCell::new(fields),
+        }
+    }
+    fn write_fields(&self, f: &mut fmt::Formatter<'_>, typedef: &ObjStruct) -> fmt::Result {
+        write!(f, "{} {{ ", typedef.name)?;
+        for (i, ((name, _), value)) in typedef
+            .fields
+            .iter()
+            .zip(self.fields.borrow().iter())
+            .enumerate()
+        {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", name, value)?;
+        }
+        write!(f, " }}")
+    }
+}
+impl From<ObjInstance> for Object {
+    fn from(i: ObjInstance) -> Self {
+        Self::Instance(i)
+    }
+}
+thread_local! {
+    /// The instances whose fields are being printed, so a cycle prints as
+    /// `Name {...}` instead of recursing forever.
+    static PRINTING: RefCell<Vec<*const ObjInstance>> = const { RefCell::new(Vec::new()) };
+}
+impl Display for ObjInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let typedef = self.typedef.get_ref().expect("valid struct");
+        let this = self as *const ObjInstance;
+        if PRINTING.with_borrow(|printing| printing.contains(&this)) {
+            return write!(f, "{} {{...}}", typedef.name);
+        }
+        PRINTING.with_borrow_mut(|printing| printing.push(this));
+        let result = self.write_fields(f, typedef);
+        PRINTING.with_borrow_mut(|printing| printing.pop());
+        result
+    }
+}
+
+/// A `typedef enum` declaration.
+#[derive(Debug)]
+pub struct ObjEnum {
+    pub name: StringPointer,
+    pub variants: Vec<(StringPointer, Number)>,
+}
+unsafe impl Send for ObjEnum {}
+unsafe impl Sync for ObjEnum {}
+impl ObjEnum {
+    pub fn new(name: StringPointer, variants: Vec<(StringPointer, Number)>) -> Self {
+        Self { name, variants }
+    }
+    pub fn discriminant(&self, name: StringPointer) -> Option<Number> {
+        self.variants
+            .iter()
+            .find(|(variant, _)| *variant == name)
+            .map(|(_, value)| *value)
+    }
+    /// The first variant declared with `value`.
+    pub fn variant_name(&self, value: Number) -> Option<StringPointer> {
+        self.variants
+            .iter()
+            .find(|(_, v)| *v == value)
+            .map(|(name, _)| *name)
+    }
+}
+impl From<ObjEnum> for Object {
+    fn from(e: ObjEnum) -> Self {
+        Self::Enum(e)
+    }
+}
+impl Display for ObjEnum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<enum {}>", self.name)
+    }
+}
+
+/// The most elements a `[start..end]` range may build.
+pub const RANGE_MAX: usize = 1 << 20;
+
+/// A fixed-size array.
+#[derive(Debug)]
+pub struct ObjArray {
+    /// The type every element shares.
+    pub element: TypeId,
+    pub elements: RefCell<Vec<Type>>,
+}
+unsafe impl Send for ObjArray {}
+unsafe impl Sync for ObjArray {}
+impl ObjArray {
+    pub fn new(element: TypeId, elements: Vec<Type>) -> Self {
+        Self {
+            element,
+            elements: RefCell::new(elements),
+        }
+    }
+}
+impl From<ObjArray> for Object {
+    fn from(a: ObjArray) -> Self {
+        Self::Array(a)
+    }
+}
+impl Display for ObjArray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, value) in self.elements.borrow().iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", value)?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// A host function callable from grim. It gets the vm and the call's
+/// arguments, and its result replaces the call on the stack.
+///
+/// `VM` stays locked while the function runs, so it must allocate through
+/// the `&mut Vm` it is given. `allocate_string!` and `allocate_object!`
+/// lock `VM` again and would spin forever.
+pub type NativeFn = fn(&mut Vm, &[Type]) -> Result<Type, VmError>;
+
+/// A Rust function bound to a grim name with `Vm::define_native`.
+#[derive(Debug)]
+pub struct ObjNative {
+    pub name: StringPointer,
+    pub arity: u8,
+    pub function: NativeFn,
+}
+unsafe impl Send for ObjNative {}
+unsafe impl Sync for ObjNative {}
+impl ObjNative {
+    pub fn new(name: StringPointer, arity: u8, function: NativeFn) -> Self {
+        Self {
+            name,
+            arity,
+            function,
+        }
+    }
+}
+impl From<ObjNative> for Object {
+    fn from(n: ObjNative) -> Self {
+        Self::Native(n)
+    }
+}
+impl Display for ObjNative {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
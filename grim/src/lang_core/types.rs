@@ -0,0 +1,157 @@
+use super::{
+    objects::{ObjectPointer, Pointable},
+    prelude::StringPointer,
+    Type,
+};
+use std::fmt::{self, Display};
+
+/// The type of a grim value as it is written in annotations.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub enum TypeId {
+    Number,
+    /// `i64`
+    Long,
+    Float,
+    Bool,
+    Char,
+    String,
+    Module,
+    Function,
+    #[default]
+    Nil,
+    Custom(StringPointer),
+    /// `[T; N]`
+    Array(Box<TypeId>, usize),
+    /// `T?`, which may also hold nil.
+    Nullable(Box<TypeId>),
+    /// A function taking the listed parameters and returning the boxed type.
+    Fn(Vec<TypeId>, Box<TypeId>),
+    /// A struct or enum declaration, as opposed to a value of that type.
+    Typedef(ObjectPointer),
+    /// Not known until runtime, where the value is checked instead.
+    Any,
+}
+
+impl TypeId {
+    /// Whether a value of type `other` may be used where `self` is expected.
+    pub fn accepts(&self, other: &TypeId) -> bool {
+        match (self, other) {
+            (Self::Any, _) | (_, Self::Any) => true,
+            (Self::Function, Self::Fn(..)) => true,
+            (Self::Array(a, n), Self::Array(b, m)) => n == m && a.accepts(b),
+            (Self::Nullable(_), Self::Nil) => true,
+            (Self::Nullable(a), Self::Nullable(b)) => a.accepts(b),
+            (Self::Nullable(a), _) => a.accepts(other),
+            _ => self == other,
+        }
+    }
+    /// Whether the runtime value `value` is of this type.
+    pub fn matches(&self, value: &Type) -> bool {
+        match (self, value) {
+            (Self::Nullable(_), Type::Nil) => true,
+            (Self::Nullable(id), _) => id.matches(value),
+            (Self::Fn(..), _) => Self::Function.matches(value),
+            (Self::Array(element, len), Type::Object(ObjectPointer::Array(a))) => {
+                let elements = a.get_ref().expect("valid array").elements.borrow();
+                elements.len() == *len && elements.iter().all(|e| element.matches(e))
+            }
+            (Self::Typedef(typedef), Type::Object(obj)) => typedef == obj,
+            _ => self.accepts(&value.type_id()),
+        }
+    }
+    /// Whether part of the type is only known at runtime.
+    pub fn has_any(&self) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Array(id, _) | Self::Nullable(id) => id.has_any(),
+            _ => false,
+        }
+    }
+    /// The type without its `?`.
+    pub fn non_null(&self) -> &TypeId {
+        match self {
+            Self::Nullable(id) => id,
+            _ => self,
+        }
+    }
+}
+
+impl Display for TypeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number => write!(f, "int"),
+            Self::Long => write!(f, "i64"),
+            Self::Float => write!(f, "float"),
+            Self::Bool => write!(f, "bool"),
+            Self::Char => write!(f, "char"),
+            Self::String => write!(f, "String"),
+            Self::Module => write!(f, "module"),
+            Self::Function => write!(f, "function"),
+            Self::Nil => write!(f, "nil"),
+            Self::Custom(name) => write!(f, "{}", name),
+            Self::Array(element, len) => write!(f, "[{}; {}]", element, len),
+            Self::Nullable(id) => write!(f, "{}?", id),
+            Self::Fn(params, returns) => {
+                write!(f, "def(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ") -: {}", returns)
+            }
+            Self::Typedef(typedef) => write!(f, "typedef {}", typedef),
+            Self::Any => write!(f, "any"),
+        }
+    }
+}
+
+/// The parameter and return types of the built-in String method `name`.
+pub fn string_method(name: &str) -> Option<(Vec<TypeId>, TypeId)> {
+    use TypeId::{Any, Bool, Number, String};
+    let signature = match name {
+        "len" | "to_int" => (vec![], Number),
+        "substr" => (vec![Number, Number], String),
+        "index_of" => (vec![String], Number),
+        "contains" | "starts_with" => (vec![String], Bool),
+        // An array of Strings, whose length is only known at runtime.
+        "split" => (vec![String], Any),
+        "trim" | "upper" | "lower" => (vec![], String),
+        "replace" => (vec![String, String], String),
+        _ => return None,
+    };
+    Some(signature)
+}
+
+impl Type {
+    pub fn type_id(&self) -> TypeId {
+        match self {
+            Self::Number(_) => TypeId::Number,
+            Self::Long(_) => TypeId::Long,
+            Self::Float(_) => TypeId::Float,
+            Self::Bool(_) => TypeId::Bool,
+            Self::Char(_) => TypeId::Char,
+            Self::Nil => TypeId::Nil,
+            Self::Enum(e, _) => TypeId::Custom(e.get_ref().expect("valid enum").name),
+            Self::Object(obj) => match obj {
+                ObjectPointer::String(_) => TypeId::String,
+                ObjectPointer::Instance(i) => {
+                    let instance = i.get_ref().expect("valid instance");
+                    TypeId::Custom(instance.typedef.get_ref().expect("valid struct").name)
+                }
+                ObjectPointer::Function(_)
+                | ObjectPointer::Closure(_)
+                | ObjectPointer::Struct(_)
+                | ObjectPointer::Upvalue(_)
+                | ObjectPointer::Native(_) => TypeId::Function,
+                ObjectPointer::Enum(_) => TypeId::Module,
+                ObjectPointer::Array(a) => {
+                    let array = a.get_ref().expect("valid array");
+                    let len = array.elements.borrow().len();
+                    TypeId::Array(Box::new(array.element.clone()), len)
+                }
+            },
+        }
+    }
+}
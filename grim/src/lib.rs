@@ -0,0 +1,10 @@
+//! The grim compiler and virtual machine, for running grim from Rust and
+//! extending it with native functions.
+mod compiler;
+mod err;
+mod lang_core;
+mod vm;
+
+pub use err::VmError;
+pub use lang_core::{objects::NativeFn, Type};
+pub use vm::{interpret, Vm, VM};
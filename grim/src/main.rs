@@ -1,14 +1,10 @@
+use grim::{interpret, VM};
 use std::{
     fs::File,
     io::{self, Read, Result, Write},
     process::exit,
 };
-mod compiler;
-mod err;
-mod lang_core;
-mod vm;
 
-use vm::interpret;
 fn run_repl() -> Result<()> {
     let mut line = String::new();
     loop {
@@ -21,7 +17,7 @@ fn run_repl() -> Result<()> {
         }
         if let Err(err) = interpret(&line) {
             eprintln!("{}", err);
-            vm::VM.lock().reset_stack();
+            VM.lock().reset_stack();
         }
         line = String::new();
     }
@@ -33,20 +29,34 @@ fn run_file(file: &str) -> Result<()> {
     file.read_to_string(&mut buffer)?;
     if let Err(err) = interpret(&buffer) {
         eprintln!("{}", err);
+        #[cfg(feature = "log_gc")]
+        VM.lock().memory.print_heap_summary();
         exit(err.1);
     }
     Ok(())
 }
 
 fn main() -> Result<()> {
-    vm::VM.lock().init();
+    {
+        let mut vm = VM.lock();
+        vm.init();
+        if let Some(bytes) = std::env::var("GRIM_GC_THRESHOLD")
+            .ok()
+            .and_then(|bytes| bytes.parse().ok())
+        {
+            vm.memory.set_gc_threshold(bytes);
+        }
+    }
     let opts = std::env::args().collect::<Vec<String>>();
-    if opts.len() == 2 {
-        run_file(&opts[0])
+    let result = if opts.len() == 2 {
+        run_file(&opts[1])
     } else if opts.len() == 1 {
         run_repl()
     } else {
         eprintln!("[usage] grim <file>");
         exit(1)
-    }
+    };
+    #[cfg(feature = "log_gc")]
+    VM.lock().memory.print_heap_summary();
+    result
 }
@@ -1,5 +1,7 @@
 use crate::lang_core::{
     chunk::{Chunk, Line, OpCode},
+    objects::{ObjectPointer, Pointable},
+    types::TypeId,
     Type,
 };
 use std::ptr;
@@ -13,6 +15,7 @@ impl From<&Chunk> for Ip {
                 current: chunk.code.as_ptr(),
                 lines: &chunk.lines,
                 constants: chunk.constants.as_ptr(),
+                types: chunk.types.as_ptr(),
             }
         }
     }
@@ -25,6 +28,7 @@ pub struct Ip {
     pub current: *const u8,
     pub lines: *const Line,
     pub constants: *const Type,
+    pub types: *const TypeId,
 }
 impl Default for Ip {
     fn default() -> Self {
@@ -50,10 +54,33 @@ impl Ip {
         unsafe { self.constants.add(loc).read() }
     }
 
-    pub fn line(&self, loc: u8) -> u32 {
+    pub fn type_id(&self, loc: u8) -> TypeId {
+        let loc = loc as usize;
+        unsafe { (*self.types.add(loc)).clone() }
+    }
+
+    pub fn line(&self, loc: usize) -> u32 {
         let line = unsafe { self.lines.as_ref().expect("initialized chunk.") };
         line.get_line(loc)
     }
+    /// Moves the instruction pointer `offset` bytes forward.
+    pub fn jump(&mut self, offset: u16) {
+        unsafe {
+            self.current = self.current.add(offset as usize);
+        }
+    }
+
+    /// Moves the instruction pointer `offset` bytes backward.
+    pub fn jump_back(&mut self, offset: u16) {
+        unsafe {
+            self.current = self.current.sub(offset as usize);
+        }
+    }
+
+    /// The offset of the next instruction from the start of the chunk.
+    pub fn offset(&self) -> usize {
+        unsafe { self.current.offset_from(self.head) as usize }
+    }
     pub const fn null() -> Self {
         let null = ptr::null();
         Self {
@@ -62,15 +89,79 @@ impl Ip {
             current: null,
             lines: ptr::null(),
             constants: ptr::null(),
+            types: ptr::null(),
         }
     }
     pub fn dissasemble_instruction(&mut self) -> (usize, String) {
         let code = OpCode::from(self.next().expect("end of file"));
         match code {
-            OpCode::Constant | OpCode::DefineGlobal | OpCode::GetGlobal | OpCode::SetGlobal => {
+            OpCode::Constant
+            | OpCode::DefineGlobal
+            | OpCode::GetGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetField
+            | OpCode::SetField => {
                 let pos = self.next().expect("end of file");
                 (2, format!("{:?}    {} '{}'", code, pos, self.constant(pos)))
             }
+            OpCode::CheckType => {
+                let pos = self.next().expect("end of file");
+                (2, format!("{:?}    {} '{}'", code, pos, self.type_id(pos)))
+            }
+            OpCode::Invoke => {
+                let pos = self.next().expect("end of file");
+                let arg_count = self.next().expect("end of file");
+                (
+                    3,
+                    format!(
+                        "{:?}    ({} args) {} '{}'",
+                        code,
+                        arg_count,
+                        pos,
+                        self.constant(pos)
+                    ),
+                )
+            }
+            OpCode::Closure => {
+                let pos = self.next().expect("end of file");
+                let constant = self.constant(pos);
+                let mut out = format!("{:?}    {} '{}'", code, pos, constant);
+                let Type::Object(ObjectPointer::Function(function)) = constant else {
+                    unreachable!();
+                };
+                let count = function.get_ref().expect("valid function").upvalue_count;
+                for _ in 0..count {
+                    let is_local = self.next().expect("end of file");
+                    let index = self.next().expect("end of file");
+                    let kind = if is_local == 1 { "local" } else { "upvalue" };
+                    out.push_str(&format!("\n            | {} {}", kind, index));
+                }
+                (2 + 2 * count as usize, out)
+            }
+            OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::GetUpvalue
+            | OpCode::SetUpvalue
+            | OpCode::Call
+            | OpCode::Array
+            | OpCode::BuildString => {
+                let slot = self.next().expect("end of file");
+                (2, format!("{:?}    {}", code, slot))
+            }
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+                let jump = u16::from_be_bytes([
+                    self.next().expect("end of file"),
+                    self.next().expect("end of file"),
+                ]) as usize;
+                let target = match code {
+                    OpCode::Loop => self.offset() - jump,
+                    _ => self.offset() + jump,
+                };
+                (
+                    3,
+                    format!("{:?}    {} -> {}", code, self.offset() - 3, target),
+                )
+            }
             _ => (1, format!("{:?}", code)),
         }
     }
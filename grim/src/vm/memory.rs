@@ -1,13 +1,14 @@
 use crate::{
     err::VmError,
     lang_core::{
-        objects::{ObjString, Object},
-        prelude::{ObjectPointer, StringPointer},
+        objects::{ObjString, Object, Pointable, UpvalueLocation},
+        prelude::{Number, ObjectPointer, StringPointer, TypeId},
         Type,
     },
 };
 use std::{
     collections::{HashMap, HashSet, LinkedList},
+    mem,
     pin::Pin,
 };
 
@@ -21,20 +22,133 @@ macro_rules! error {
     }
 }
 
+/// The number of bytes that may be allocated before the first collection.
+const GC_INITIAL_THRESHOLD: usize = 1024 * 1024;
+/// How much the heap may grow past the live set before the next collection.
+const GC_HEAP_GROW_FACTOR: usize = 2;
+
 pub struct Memory {
     globals: Option<HashMap<StringPointer, Type>>,
-    strings: Option<HashSet<ObjString>>,
-    objects: LinkedList<Pin<Box<Object>>>,
+    /// The static types of the globals, kept across compilations so the
+    /// repl can check code against earlier lines.
+    global_types: Option<HashMap<StringPointer, TypeId>>,
+    /// Interned strings are boxed so that a [`StringPointer`] stays valid when
+    /// the table grows.
+    strings: Option<HashSet<Box<ObjString>>>,
+    /// Every object with the size `track` counted for it. Objects such as
+    /// arrays can grow afterwards, so `sweep` must debit this size rather
+    /// than measure them again.
+    objects: LinkedList<(Pin<Box<Object>>, usize)>,
+    /// Everything allocated while compiling, kept alive until the compiled
+    /// script is handed to the vm.
+    compiler_roots: Vec<ObjectPointer>,
+    compiling: bool,
+    bytes_allocated: usize,
+    next_gc: usize,
+    #[cfg(feature = "log_gc")]
+    stats: GcStats,
+}
+
+#[cfg(feature = "log_gc")]
+struct GcStats {
+    collections: usize,
+    total_allocated: usize,
+    total_freed: usize,
+}
+
+/// The name `log_gc` reports an object under.
+#[cfg(feature = "log_gc")]
+fn object_kind(obj: &Object) -> &'static str {
+    match obj {
+        Object::String(_) => "string",
+        Object::Function(_) => "function",
+        Object::Closure(_) => "closure",
+        Object::Upvalue(_) => "upvalue",
+        Object::Struct(_) => "struct",
+        Object::Instance(_) => "instance",
+        Object::Enum(_) => "enum",
+        Object::Array(_) => "array",
+        Object::Native(_) => "native",
+    }
+}
+
+/// An estimate of the bytes owned by `obj`.
+fn object_size(obj: &Object) -> usize {
+    mem::size_of::<Object>()
+        + match obj {
+            Object::String(s) => s.len(),
+            Object::Function(f) => {
+                f.chunk.code.len()
+                    + f.chunk.constants.len() * mem::size_of::<Type>()
+                    + f.chunk.lines.lines.len() * mem::size_of::<(u32, u32)>()
+                    + f.chunk.types.len() * mem::size_of::<TypeId>()
+            }
+            Object::Closure(c) => c.upvalues.len() * mem::size_of::<ObjectPointer>(),
+            Object::Upvalue(_) => 0,
+            Object::Struct(s) => s.fields.len() * mem::size_of::<(StringPointer, TypeId)>(),
+            Object::Instance(i) => i.fields.borrow().len() * mem::size_of::<Type>(),
+            Object::Enum(e) => e.variants.len() * mem::size_of::<(StringPointer, Number)>(),
+            Object::Array(a) => a.elements.borrow().len() * mem::size_of::<Type>(),
+            Object::Native(_) => 0,
+        }
+}
+
+fn string_size(s: &ObjString) -> usize {
+    mem::size_of::<ObjString>() + s.len()
 }
+
 impl Memory {
     pub const fn new() -> Self {
         Self {
             globals: None,
+            global_types: None,
             strings: None,
             objects: LinkedList::new(),
+            compiler_roots: Vec::new(),
+            compiling: false,
+            bytes_allocated: 0,
+            next_gc: GC_INITIAL_THRESHOLD,
+            #[cfg(feature = "log_gc")]
+            stats: GcStats {
+                collections: 0,
+                total_allocated: 0,
+                total_freed: 0,
+            },
+        }
+    }
+    /// Sets how many bytes may be allocated before the next collection.
+    pub fn set_gc_threshold(&mut self, bytes: usize) {
+        self.next_gc = bytes;
+    }
+    pub fn should_collect(&self) -> bool {
+        cfg!(feature = "stress_gc") || self.bytes_allocated > self.next_gc
+    }
+    /// Roots every object allocated from now until [`Memory::end_compile`].
+    pub fn begin_compile(&mut self) {
+        self.compiling = true;
+    }
+    pub fn end_compile(&mut self) {
+        self.compiling = false;
+        self.compiler_roots.clear();
+    }
+    fn track(&mut self, obj: ObjectPointer, size: usize) {
+        self.bytes_allocated += size;
+        #[cfg(feature = "log_gc")]
+        if size > 0 {
+            self.stats.total_allocated += size;
+            println!("{:?} allocate {} bytes", obj, size);
+        }
+        if self.compiling {
+            self.compiler_roots.push(obj);
         }
     }
     pub fn allocate_string(&mut self, string: &str) -> StringPointer {
+        let ptr = self.intern_string(string);
+        // New strings are accounted for when interned.
+        self.track(ptr.into(), 0);
+        ptr
+    }
+    fn intern_string(&mut self, string: &str) -> StringPointer {
         let key = ObjString::new(string);
         match self
             .strings
@@ -43,19 +157,27 @@ impl Memory {
             .get(&key)
         {
             None => {
+                let size = string_size(&key);
+                self.bytes_allocated += size;
+                #[cfg(feature = "log_gc")]
+                {
+                    self.stats.total_allocated += size;
+                    println!("{:?} allocate {} bytes for string", string, size);
+                }
                 self.strings
                     .as_mut()
                     .expect("could not get table")
-                    .insert(key.clone());
+                    .insert(Box::new(key.clone()));
                 StringPointer::new(
-                    self.strings
+                    &**self
+                        .strings
                         .as_ref()
                         .expect("Could not get table")
                         .get(&key)
                         .unwrap(),
                 )
             }
-            Some(s) => StringPointer::new(s),
+            Some(s) => StringPointer::new(&**s),
         }
     }
     pub fn set_global(&mut self, key: StringPointer, value: Type) -> Option<Type> {
@@ -69,9 +191,10 @@ impl Memory {
                         self.remove_global(key);
                         return error!("Undefined variable '{}'", key);
         };
-        if !old.types_equal(&value) {
+        let id = self.get_global_type(key).unwrap_or(TypeId::Any);
+        if !id.matches(&value) {
             self.set_global(key, old);
-            error!("Type mismatch.")
+            error!("Expected {}, found {}.", id, value.type_id())
         } else {
             Ok(())
         }
@@ -85,32 +208,293 @@ impl Memory {
             None => None,
         }
     }
-    pub fn allocate_object<T: Into<Object>>(&'static mut self, obj: T) -> ObjectPointer {
-        self.objects.push_back(Box::pin(obj.into()));
-        self.objects.back().expect("could not allocate object");
-        ObjectPointer::from(
+    pub fn set_global_type(&mut self, key: StringPointer, id: TypeId) {
+        self.global_types
+            .as_mut()
+            .expect("initialized vm")
+            .insert(key, id);
+    }
+    pub fn get_global_type(&self, key: StringPointer) -> Option<TypeId> {
+        self.global_types
+            .as_ref()
+            .expect("initialized vm")
+            .get(&key)
+            .cloned()
+    }
+    pub fn allocate_object<T: Into<Object>>(&mut self, obj: T) -> ObjectPointer {
+        let obj = obj.into();
+        let size = object_size(&obj);
+        self.objects.push_back((Box::pin(obj), size));
+        let ptr = ObjectPointer::from(
             self.objects
                 .back()
                 .expect("could not get object")
+                .0
                 .as_ref()
                 .get_ref(),
-        )
+        );
+        self.track(ptr, size);
+        ptr
+    }
+    /// Frees every object that can't be reached from `roots`, the globals
+    /// or the compiler.
+    pub fn collect_garbage<I: IntoIterator<Item = ObjectPointer>>(&mut self, roots: I) {
+        #[cfg(feature = "log_gc")]
+        let before = self.bytes_allocated;
+        #[cfg(feature = "log_gc")]
+        println!("-- gc begin");
+
+        let mut gray: Vec<ObjectPointer> = roots.into_iter().collect();
+        gray.extend(self.compiler_roots.iter().copied());
+        for (key, value) in self.globals.as_ref().expect("initialized vm") {
+            gray.push((*key).into());
+            gray.extend(objects_in(&[*value]));
+        }
+        for (key, id) in self.global_types.as_ref().expect("initialized vm") {
+            gray.push((*key).into());
+            objects_in_type(id, &mut gray);
+        }
+
+        let mut marked = HashSet::new();
+        while let Some(obj) = gray.pop() {
+            if marked.insert(obj) {
+                blacken(obj, &mut gray);
+            }
+        }
+        self.sweep(&marked);
+        self.next_gc = self.bytes_allocated * GC_HEAP_GROW_FACTOR;
+
+        #[cfg(feature = "log_gc")]
+        {
+            self.stats.collections += 1;
+            println!(
+                "-- gc end: collected {} bytes (from {} to {}) next at {}",
+                before - self.bytes_allocated,
+                before,
+                self.bytes_allocated,
+                self.next_gc
+            );
+        }
+    }
+    /// Prints the totals gathered by `log_gc` and what is still live.
+    #[cfg(feature = "log_gc")]
+    pub fn print_heap_summary(&self) {
+        let mut live: Vec<(&str, usize)> = Vec::new();
+        let strings = self.strings.as_ref().map_or(0, |strings| strings.len());
+        live.push(("string", strings));
+        for (obj, _) in &self.objects {
+            let kind = object_kind(obj);
+            match live.iter_mut().find(|(k, _)| *k == kind) {
+                Some((_, count)) => *count += 1,
+                None => live.push((kind, 1)),
+            }
+        }
+        println!("== heap summary ==");
+        println!("collections:     {}", self.stats.collections);
+        println!("total allocated: {} bytes", self.stats.total_allocated);
+        println!("total freed:     {} bytes", self.stats.total_freed);
+        println!("live:            {} bytes", self.bytes_allocated);
+        for (kind, count) in live {
+            println!("  {:<14} {}", kind, count);
+        }
+    }
+    fn sweep(&mut self, marked: &HashSet<ObjectPointer>) {
+        let mut freed = 0;
+        let objects = mem::take(&mut self.objects);
+        for (obj, size) in objects {
+            let ptr = ObjectPointer::from(obj.as_ref().get_ref());
+            if marked.contains(&ptr) {
+                self.objects.push_back((obj, size));
+            } else {
+                #[cfg(feature = "log_gc")]
+                println!("{:?} free {} bytes for {}", ptr, size, object_kind(&obj));
+                freed += size;
+            }
+        }
+        self.strings
+            .as_mut()
+            .expect("could not get table")
+            .retain(|s| {
+                let ptr = StringPointer::new(&**s).into();
+                let keep = marked.contains(&ptr);
+                if !keep {
+                    #[cfg(feature = "log_gc")]
+                    println!("{:?} free {} bytes for string", ptr, string_size(s));
+                    freed += string_size(s);
+                }
+                keep
+            });
+        self.bytes_allocated -= freed;
+        #[cfg(feature = "log_gc")]
+        {
+            self.stats.total_freed += freed;
+        }
     }
     pub fn initialize_memory(&mut self) {
         _ = self.globals.insert(HashMap::new());
+        _ = self.global_types.insert(HashMap::new());
         _ = self.strings.insert(HashSet::new());
     }
 }
+/// The objects referenced by `values`.
+pub fn objects_in(values: &[Type]) -> impl Iterator<Item = ObjectPointer> + '_ {
+    values.iter().filter_map(|value| match value {
+        Type::Object(obj) => Some(*obj),
+        Type::Enum(e, _) => Some((*e).into()),
+        _ => None,
+    })
+}
+
+/// Pushes the struct and enum names and declarations `id` refers to onto
+/// `gray`.
+fn objects_in_type(id: &TypeId, gray: &mut Vec<ObjectPointer>) {
+    match id {
+        TypeId::Custom(name) => gray.push((*name).into()),
+        TypeId::Typedef(typedef) => gray.push(*typedef),
+        TypeId::Array(element, _) | TypeId::Nullable(element) => objects_in_type(element, gray),
+        TypeId::Fn(params, returns) => {
+            for param in params {
+                objects_in_type(param, gray);
+            }
+            objects_in_type(returns, gray);
+        }
+        _ => {}
+    }
+}
+
+/// Pushes every object directly referenced by `obj` onto `gray`.
+fn blacken(obj: ObjectPointer, gray: &mut Vec<ObjectPointer>) {
+    match obj {
+        ObjectPointer::String(_) => {}
+        ObjectPointer::Function(f) => {
+            let f = f.get_ref().expect("valid function");
+            gray.extend(objects_in(&f.chunk.constants));
+            for id in &f.chunk.types {
+                objects_in_type(id, gray);
+            }
+            if let Some(name) = f.name {
+                gray.push(name.into());
+            }
+        }
+        ObjectPointer::Closure(c) => {
+            let c = c.get_ref().expect("valid closure");
+            gray.push(c.function.into());
+            gray.extend(c.upvalues.iter().map(|&u| ObjectPointer::from(u)));
+        }
+        ObjectPointer::Upvalue(u) => {
+            let u = u.get_ref().expect("valid upvalue");
+            if let UpvalueLocation::Closed(value) = u.location.get() {
+                gray.extend(objects_in(&[value]));
+            }
+        }
+        ObjectPointer::Struct(s) => {
+            let s = s.get_ref().expect("valid struct");
+            gray.push(s.name.into());
+            for (name, id) in &s.fields {
+                gray.push((*name).into());
+                objects_in_type(id, gray);
+            }
+        }
+        ObjectPointer::Instance(i) => {
+            let i = i.get_ref().expect("valid instance");
+            gray.push(i.typedef.into());
+            gray.extend(objects_in(&i.fields.borrow()));
+        }
+        ObjectPointer::Enum(e) => {
+            let e = e.get_ref().expect("valid enum");
+            gray.push(e.name.into());
+            gray.extend(
+                e.variants
+                    .iter()
+                    .map(|(name, _)| ObjectPointer::from(*name)),
+            );
+        }
+        ObjectPointer::Array(a) => {
+            let a = a.get_ref().expect("valid array");
+            objects_in_type(&a.element, gray);
+            gray.extend(objects_in(&a.elements.borrow()));
+        }
+        ObjectPointer::Native(n) => {
+            let n = n.get_ref().expect("valid native");
+            gray.push(n.name.into());
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! allocate_object {
     ($obj: expr) => {
-        crate::vm::VM.lock().memory.allocate_object($obj)
+        $crate::vm::VM.lock().allocate_object($obj)
     };
 }
 
 #[macro_export]
 macro_rules! allocate_string {
     ($str: expr) => {
-        crate::vm::VM.lock().memory.allocate_string($str)
+        $crate::vm::VM.lock().allocate_string($str)
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::object_size;
+    use crate::{
+        lang_core::{objects::Pointable, prelude::*},
+        vm::{interpret, VM},
+    };
+
+    fn global(name: &str) -> Type {
+        let mut vm = VM.lock();
+        let name = vm.memory.allocate_string(name);
+        vm.memory.get_global(name).expect("defined global")
+    }
+
+    /// Collects after almost every allocation while a script keeps
+    /// strings, a closure's upvalue and arrays alive through globals, and
+    /// splits strings into arrays that are freed again. Every object must
+    /// be the size it was tracked with, or sweeping it miscounts.
+    #[test]
+    fn strings_closures_and_arrays_survive_collection() {
+        {
+            let mut vm = VM.lock();
+            vm.init();
+            vm.memory.set_gc_threshold(1);
+        }
+        let commas = ",".repeat(200);
+        let source = r#"
+            def make_counter() {
+                bind n = 0;
+                def inc() { n += 1; return n; }
+                return inc;
+            }
+            bind counter = make_counter();
+            bind text = "";
+            bind words = ["a", "b", "c"];
+            for (bind i = 0; i < 300; i += 1) {
+                text = text + "x";
+                counter();
+                bind garbage = "tmp ${i}";
+                bind numbers = [i, i + 1];
+            }
+            bind count = counter();
+            for (bind i = 0; i < 20; i += 1) {
+                "COMMAS".split(",");
+            }
+            bind parts = "a,b,,c".split(",");
+        "#
+        .replace("COMMAS", &commas);
+        interpret(&source).expect("script runs");
+
+        let Type::Object(ObjectPointer::String(text)) = global("text") else {
+            panic!("text is not a string");
+        };
+        assert_eq!(&**text.get_ref().expect("live string"), "x".repeat(300));
+        assert_eq!(global("count"), Type::Number(301));
+        assert_eq!(global("words").to_string(), "[a, b, c]");
+        assert_eq!(global("parts").to_string(), "[a, b, , c]");
+        let vm = VM.lock();
+        for (obj, size) in &vm.memory.objects {
+            assert_eq!(*size, object_size(obj), "{:?} changed size", obj);
+        }
+    }
+}
@@ -3,7 +3,7 @@ use crate::{
     err::VmError,
     lang_core::{objects::Pointable, prelude::*},
 };
-use std::{pin::Pin, result};
+use std::{fmt::Display, mem, result};
 
 pub mod ip;
 pub mod memory;
@@ -22,31 +22,76 @@ macro_rules! error {
         VmError::new($string.into())
     }
 }
+mod natives;
+mod strings;
+
+const FRAMES_MAX: usize = 64;
+const STACK_MAX: usize = FRAMES_MAX * (u8::MAX as usize + 1);
+
+#[derive(Clone, Copy)]
+struct CallFrame {
+    closure: ClosurePointer,
+    /// Where to resume once the callee returns.
+    ip: Ip,
+    /// Index of the frame's first stack slot.
+    slots: usize,
+}
 
-const STACK_MAX: usize = 255;
 pub struct Vm {
     ip: Ip,
+    frames: Vec<CallFrame>,
+    /// Upvalues still pointing at live stack slots, ordered by slot.
+    open_upvalues: Vec<UpvaluePointer>,
     stack: [Type; STACK_MAX],
     stack_top: usize,
     pub memory: Memory,
-    chunk: Option<Pin<Box<Chunk>>>,
 }
 unsafe impl Send for Vm {}
 unsafe impl Sync for Vm {}
 
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Vm {
     pub const fn new() -> Self {
         Self {
             ip: Ip::null(),
+            frames: Vec::new(),
+            open_upvalues: Vec::new(),
             stack: [Type::Nil; STACK_MAX],
             stack_top: 0,
             memory: Memory::new(),
-            chunk: None,
         }
     }
 
     pub fn init(&mut self) {
         self.memory.initialize_memory();
+        self.define_native("clock", 0, natives::clock);
+    }
+
+    pub fn allocate_object<T: Into<Object>>(&mut self, obj: T) -> ObjectPointer {
+        if self.memory.should_collect() {
+            self.collect_garbage();
+        }
+        self.memory.allocate_object(obj)
+    }
+
+    pub fn allocate_string(&mut self, string: &str) -> StringPointer {
+        if self.memory.should_collect() {
+            self.collect_garbage();
+        }
+        self.memory.allocate_string(string)
+    }
+
+    fn collect_garbage(&mut self) {
+        let stack = memory::objects_in(&self.stack[..self.stack_top]);
+        let frames = self.frames.iter().map(|frame| frame.closure.into());
+        let upvalues = self.open_upvalues.iter().map(|&upvalue| upvalue.into());
+        let roots: Vec<ObjectPointer> = stack.chain(frames).chain(upvalues).collect();
+        self.memory.collect_garbage(roots);
     }
     fn push<T: Into<Type>>(&mut self, val: T) {
         self.stack_top += 1;
@@ -65,6 +110,10 @@ impl Vm {
         self.ip.next().expect("end of file")
     }
 
+    fn read_short(&mut self) -> u16 {
+        u16::from_be_bytes([self.read_byte(), self.read_byte()])
+    }
+
     fn read_constant(&mut self) -> Type {
         let loc = self.read_byte();
         self.ip.constant(loc)
@@ -80,9 +129,204 @@ impl Vm {
     }
     pub fn reset_stack(&mut self) {
         self.stack_top = 0;
+        self.frames.clear();
+        self.open_upvalues.clear();
+    }
+
+    /// The absolute stack index of local `slot` in the current frame.
+    fn slot(&self, slot: u8) -> usize {
+        self.frames.last().expect("active frame").slots + slot as usize
+    }
+
+    fn closure(&self) -> &'static ObjClosure {
+        let frame = self.frames.last().expect("active frame");
+        frame.closure.get_ref().expect("valid closure")
+    }
+
+    fn call(&mut self, closure: ClosurePointer, arg_count: u8) -> Result<()> {
+        let closure_ref = closure.get_ref().expect("valid closure");
+        let fun = closure_ref.function.get_ref().expect("valid function");
+        if arg_count != fun.arity {
+            return error!("Expected {} arguments but got {}.", fun.arity, arg_count);
+        }
+        if self.frames.len() == FRAMES_MAX {
+            return error!("Stack overflow.");
+        }
+        if let Some(frame) = self.frames.last_mut() {
+            frame.ip = self.ip;
+        }
+        self.frames.push(CallFrame {
+            closure,
+            ip: Ip::from(&fun.chunk),
+            slots: self.stack_top - arg_count as usize - 1,
+        });
+        self.ip = Ip::from(&fun.chunk);
+        Ok(())
+    }
+
+    fn call_value(&mut self, callee: Type, arg_count: u8) -> Result<()> {
+        match callee {
+            Type::Object(ObjectPointer::Closure(closure)) => self.call(closure, arg_count),
+            Type::Object(ObjectPointer::Struct(typedef)) => self.instantiate(typedef, arg_count),
+            Type::Object(ObjectPointer::Native(native)) => self.call_native(native, arg_count),
+            _ => error!("Can only call functions and structs."),
+        }
+    }
+
+    /// Replaces a native function and its `arg_count` arguments on the
+    /// stack with its result.
+    fn call_native(&mut self, native: NativePointer, arg_count: u8) -> Result<()> {
+        let native = native.get_ref().expect("valid native");
+        if arg_count != native.arity {
+            return error!("Expected {} arguments but got {}.", native.arity, arg_count);
+        }
+        let arg_count = arg_count as usize;
+        let args = self.stack[self.stack_top - arg_count..self.stack_top].to_vec();
+        let result = (native.function)(self, &args)?;
+        self.stack_top -= arg_count + 1;
+        self.push(result);
+        Ok(())
+    }
+
+    /// Binds the Rust function `function` to the global `name`, so grim
+    /// code can call it with `arity` arguments of any type. See [`NativeFn`]
+    /// for what the function may do with the vm.
+    pub fn define_native(&mut self, name: &str, arity: u8, function: NativeFn) {
+        // Rooted on the stack until bound, as either allocation may collect.
+        let name = self.allocate_string(name);
+        self.push(name);
+        let native = self.allocate_object(ObjNative::new(name, arity, function));
+        self.push(native);
+        self.memory.set_global(name, native.into());
+        let signature = TypeId::Fn(vec![TypeId::Any; arity as usize], Box::new(TypeId::Any));
+        self.memory.set_global_type(name, signature);
+        self.pop();
+        self.pop();
+    }
+
+    /// Replaces a struct and its `arg_count` field values on the stack with a
+    /// new instance.
+    fn instantiate(&mut self, typedef: StructPointer, arg_count: u8) -> Result<()> {
+        let def = typedef.get_ref().expect("valid struct");
+        let arg_count = arg_count as usize;
+        if arg_count != def.fields.len() {
+            return error!(
+                "Expected {} arguments but got {}.",
+                def.fields.len(),
+                arg_count
+            );
+        }
+        let fields = self.stack[self.stack_top - arg_count..self.stack_top].to_vec();
+        for (index, value) in fields.iter().enumerate() {
+            check_field(def, index, value)?;
+        }
+        let instance = self.allocate_object(ObjInstance::new(typedef, fields));
+        self.stack_top -= arg_count + 1;
+        self.push(instance);
+        Ok(())
+    }
+
+    /// Calls the method `name` on the receiver below the `arg_count`
+    /// arguments on the stack.
+    fn invoke(&mut self, name: StringPointer, arg_count: u8) -> Result<()> {
+        let receiver = self.peek(arg_count as usize);
+        match receiver {
+            Type::Object(ObjectPointer::Instance(instance)) => {
+                let instance = instance.get_ref().expect("valid instance");
+                let def = instance.typedef.get_ref().expect("valid struct");
+                let Some(index) = def.field(name) else {
+                    return error!("Undefined field '{}' on {}.", name, def.name);
+                };
+                let callee = instance.fields.borrow()[index];
+                self.stack[self.stack_top - arg_count as usize - 1] = callee;
+                self.call_value(callee, arg_count)
+            }
+            Type::Object(ObjectPointer::Array(array)) => {
+                let array = array.get_ref().expect("valid array");
+                let method = name.get_ref().expect("valid string");
+                match &**method {
+                    "len" if arg_count == 0 => {
+                        let len = array.elements.borrow().len() as Number;
+                        self.pop();
+                        self.push(len);
+                        Ok(())
+                    }
+                    "len" => error!("Expected 0 arguments but got {}.", arg_count),
+                    _ => error!("Undefined method '{}' on array.", name),
+                }
+            }
+            Type::Object(ObjectPointer::String(string)) => {
+                self.invoke_string(string, name, arg_count)
+            }
+            _ => error!("Only instances, arrays and strings have methods."),
+        }
+    }
+
+    /// Checks that `index` is a valid position in `array`.
+    fn index(&self, array: Type, index: Type) -> Result<(&'static ObjArray, usize)> {
+        let Type::Object(ObjectPointer::Array(array)) = array else {
+            return error!("Only arrays can be indexed.");
+        };
+        let Type::Number(index) = index else {
+            return error!("Array index must be a number.");
+        };
+        let array = array.get_ref().expect("valid array");
+        let len = array.elements.borrow().len();
+        if index < 0 || index as usize >= len {
+            return error!("Index {} out of bounds for array of length {}.", index, len);
+        }
+        Ok((array, index as usize))
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> UpvaluePointer {
+        let open = |upvalue: &UpvaluePointer| match upvalue
+            .get_ref()
+            .expect("valid upvalue")
+            .location
+            .get()
+        {
+            UpvalueLocation::Open(s) => s,
+            UpvalueLocation::Closed(_) => unreachable!(),
+        };
+        let index = self.open_upvalues.partition_point(|u| open(u) < slot);
+        if let Some(upvalue) = self.open_upvalues.get(index) {
+            if open(upvalue) == slot {
+                return *upvalue;
+            }
+        }
+        let ObjectPointer::Upvalue(upvalue) = self.allocate_object(ObjUpvalue::new(slot)) else {
+            unreachable!();
+        };
+        self.open_upvalues.insert(index, upvalue);
+        upvalue
+    }
+
+    /// Moves every captured variable at or above `last` off the stack.
+    fn close_upvalues(&mut self, last: usize) {
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let upvalue = upvalue.get_ref().expect("valid upvalue");
+            let UpvalueLocation::Open(slot) = upvalue.location.get() else {
+                unreachable!();
+            };
+            if slot < last {
+                break;
+            }
+            upvalue
+                .location
+                .set(UpvalueLocation::Closed(self.stack[slot]));
+            self.open_upvalues.pop();
+        }
     }
 
     pub fn run(&mut self) -> Result<()> {
+        self.execute().map_err(|err| {
+            // The failing instruction is the last one read.
+            let line = self.ip.line(self.ip.offset() - 1);
+            err.at_line(line)
+        })
+    }
+
+    fn execute(&mut self) -> Result<()> {
         loop {
             #[cfg(feature = "trace_execution")]
             {
@@ -112,8 +356,216 @@ impl Vm {
                     };
                     self.push(value);
                 }
+                OpCode::GetLocal => {
+                    let slot = self.read_byte();
+                    self.push(self.stack[self.slot(slot)]);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.read_byte();
+                    let slot = self.slot(slot);
+                    self.stack[slot] = self.peek(0);
+                }
+                OpCode::CheckType => {
+                    let loc = self.read_byte();
+                    let id = self.ip.type_id(loc);
+                    let value = self.peek(0);
+                    if !id.matches(&value) {
+                        return error!("Expected {}, found {}.", id, value.type_id());
+                    }
+                }
+                OpCode::Jump => {
+                    let offset = self.read_short();
+                    self.ip.jump(offset);
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_short();
+                    if self.peek(0).is_falsy() {
+                        self.ip.jump(offset);
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_short();
+                    self.ip.jump_back(offset);
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = self.closure().upvalues[index]
+                        .get_ref()
+                        .expect("valid upvalue");
+                    let value = match upvalue.location.get() {
+                        UpvalueLocation::Open(slot) => self.stack[slot],
+                        UpvalueLocation::Closed(value) => value,
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = self.closure().upvalues[index]
+                        .get_ref()
+                        .expect("valid upvalue");
+                    let value = self.peek(0);
+                    match upvalue.location.get() {
+                        UpvalueLocation::Open(slot) => self.stack[slot] = value,
+                        UpvalueLocation::Closed(_) => {
+                            upvalue.location.set(UpvalueLocation::Closed(value))
+                        }
+                    }
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack_top - 1);
+                    self.pop();
+                }
+                OpCode::Closure => {
+                    let Type::Object(ObjectPointer::Function(function)) = self.read_constant()
+                    else {
+                        panic!("Unrecoverable compiler error.");
+                    };
+                    let count = function.get_ref().expect("valid function").upvalue_count;
+                    let mut upvalues = Vec::with_capacity(count as usize);
+                    for _ in 0..count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte();
+                        let upvalue = if is_local {
+                            self.capture_upvalue(self.slot(index))
+                        } else {
+                            self.closure().upvalues[index as usize]
+                        };
+                        upvalues.push(upvalue);
+                    }
+                    let closure = self.allocate_object(ObjClosure::new(function, upvalues));
+                    self.push(closure);
+                }
+                OpCode::GetField => {
+                    let name = self.read_string();
+                    if let Type::Object(ObjectPointer::Enum(e)) = self.peek(0) {
+                        let def = e.get_ref().expect("valid enum");
+                        let Some(value) = def.discriminant(name) else {
+                            return error!("Undefined variant '{}' on {}.", name, def.name);
+                        };
+                        self.pop();
+                        self.push(Type::Enum(e, value));
+                        continue;
+                    }
+                    let Type::Object(ObjectPointer::Instance(instance)) = self.peek(0) else {
+                        return error!("Only struct instances have fields.");
+                    };
+                    let instance = instance.get_ref().expect("valid instance");
+                    let def = instance.typedef.get_ref().expect("valid struct");
+                    let Some(index) = def.field(name) else {
+                        return error!("Undefined field '{}' on {}.", name, def.name);
+                    };
+                    let value = instance.fields.borrow()[index];
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::SetField => {
+                    let name = self.read_string();
+                    let Type::Object(ObjectPointer::Instance(instance)) = self.peek(1) else {
+                        return error!("Only struct instances have fields.");
+                    };
+                    let instance = instance.get_ref().expect("valid instance");
+                    let def = instance.typedef.get_ref().expect("valid struct");
+                    let Some(index) = def.field(name) else {
+                        return error!("Undefined field '{}' on {}.", name, def.name);
+                    };
+                    let value = self.pop();
+                    check_field(def, index, &value)?;
+                    instance.fields.borrow_mut()[index] = value;
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::Call => {
+                    let arg_count = self.read_byte();
+                    self.call_value(self.peek(arg_count as usize), arg_count)?;
+                }
+                OpCode::Invoke => {
+                    let name = self.read_string();
+                    let arg_count = self.read_byte();
+                    self.invoke(name, arg_count)?;
+                }
+                OpCode::Array => {
+                    let count = self.read_byte() as usize;
+                    let elements = self.stack[self.stack_top - count..self.stack_top].to_vec();
+                    let mut values = elements.iter().filter(|value| **value != Type::Nil);
+                    let element = values.next().map(Type::type_id).unwrap_or_default();
+                    if values.any(|value| value.type_id() != element) {
+                        return error!("Array elements must all have the same type.");
+                    }
+                    let element = match elements.contains(&Type::Nil) {
+                        true if element != TypeId::Nil => TypeId::Nullable(Box::new(element)),
+                        _ => element,
+                    };
+                    let array = self.allocate_object(ObjArray::new(element, elements));
+                    self.stack_top -= count;
+                    self.push(array);
+                }
+                OpCode::BuildString => {
+                    let count = self.read_byte() as usize;
+                    // The parts stay on the stack, where the collector sees
+                    // them, until the string is allocated.
+                    let string: String = self.stack[self.stack_top - count..self.stack_top]
+                        .iter()
+                        .map(Type::to_string)
+                        .collect();
+                    let string = self.allocate_string(&string);
+                    self.stack_top -= count;
+                    self.push(string);
+                }
+                OpCode::Range => {
+                    let (start, end) = (self.peek(1), self.peek(0));
+                    let len = match (start, end) {
+                        (Type::Number(a), Type::Number(b)) => b as i64 - a as i64 + 1,
+                        (Type::Char(a), Type::Char(b)) => b as i64 - a as i64 + 1,
+                        _ => return error!("Range bounds must be two numbers or two chars."),
+                    };
+                    if len < 1 {
+                        return error!("Range start {} is greater than its end {}.", start, end);
+                    }
+                    if len as usize > RANGE_MAX {
+                        return error!("Range of {} elements is too large.", len);
+                    }
+                    let (element, elements) = match (start, end) {
+                        (Type::Number(a), Type::Number(b)) => {
+                            (TypeId::Number, (a..=b).map(Type::Number).collect())
+                        }
+                        (Type::Char(a), Type::Char(b)) => {
+                            (TypeId::Char, (a..=b).map(Type::Char).collect())
+                        }
+                        _ => unreachable!(),
+                    };
+                    let array = self.allocate_object(ObjArray::new(element, elements));
+                    self.pop();
+                    self.pop();
+                    self.push(array);
+                }
+                OpCode::GetIndex => {
+                    let (array, index) = self.index(self.peek(1), self.peek(0))?;
+                    let value = array.elements.borrow()[index];
+                    self.pop();
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::SetIndex => {
+                    let (array, index) = self.index(self.peek(2), self.peek(1))?;
+                    // The compiler checked the value against the array's
+                    // declared element type.
+                    let value = self.pop();
+                    array.elements.borrow_mut()[index] = value;
+                    self.pop();
+                    self.pop();
+                    self.push(value);
+                }
                 OpCode::Return => {
-                    return Ok(());
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("active frame");
+                    self.close_upvalues(frame.slots);
+                    let Some(caller) = self.frames.last() else {
+                        self.pop();
+                        return Ok(());
+                    };
+                    self.ip = caller.ip;
+                    self.stack_top = frame.slots;
+                    self.push(result);
                 }
                 OpCode::Constant => {
                     let val = self.read_constant();
@@ -122,55 +574,143 @@ impl Vm {
                 OpCode::Subtract
                 | OpCode::Divide
                 | OpCode::Multiply
+                | OpCode::Modulo
+                | OpCode::Power
                 | OpCode::Greater
-                | OpCode::Less => match (self.pop(), self.pop()) {
-                    (Type::Number(b), Type::Number(a)) => {
+                | OpCode::GreaterEqual
+                | OpCode::Less
+                | OpCode::LessEqual => match promote(self.pop(), self.pop()) {
+                    Operands::Floats(b, a) => {
                         let n: Type = match byte {
                             OpCode::Less => (a < b).into(),
+                            OpCode::LessEqual => (a <= b).into(),
                             OpCode::Greater => (a > b).into(),
+                            OpCode::GreaterEqual => (a >= b).into(),
                             OpCode::Subtract => (a - b).into(),
                             OpCode::Divide => (a / b).into(),
                             OpCode::Multiply => (a * b).into(),
+                            OpCode::Modulo => (a % b).into(),
+                            OpCode::Power => a.powf(b).into(),
                             _ => unreachable!(),
                         };
                         self.push(n);
                     }
+                    Operands::Longs(b, a) => {
+                        let n: Type = match byte {
+                            OpCode::Less => (a < b).into(),
+                            OpCode::LessEqual => (a <= b).into(),
+                            OpCode::Greater => (a > b).into(),
+                            OpCode::GreaterEqual => (a >= b).into(),
+                            _ => arithmetic(&byte, a, b)?.into(),
+                        };
+                        self.push(n);
+                    }
+                    Operands::Other(Type::Number(b), Type::Number(a)) => {
+                        let n: Type = match byte {
+                            OpCode::Less => (a < b).into(),
+                            OpCode::LessEqual => (a <= b).into(),
+                            OpCode::Greater => (a > b).into(),
+                            OpCode::GreaterEqual => (a >= b).into(),
+                            _ => arithmetic(&byte, a, b)?.into(),
+                        };
+                        self.push(n);
+                    }
+                    Operands::Other(Type::Char(b), Type::Char(a)) => {
+                        let n: Type = match byte {
+                            OpCode::Less => (a < b).into(),
+                            OpCode::LessEqual => (a <= b).into(),
+                            OpCode::Greater => (a > b).into(),
+                            OpCode::GreaterEqual => (a >= b).into(),
+                            OpCode::Subtract => (a as Number - b as Number).into(),
+                            _ => return error!("Operands must be two numbers"),
+                        };
+                        self.push(n);
+                    }
+                    Operands::Other(Type::Number(b), Type::Char(a))
+                        if matches!(byte, OpCode::Subtract) =>
+                    {
+                        let Some(by) = b.checked_neg() else {
+                            return error!("Integer overflow: -({}).", b);
+                        };
+                        self.push(shift_char(a, by)?);
+                    }
                     _ => return error!("Operands must be two numbers"),
                 },
-                OpCode::Add => match (self.peek(0), self.peek(1)) {
-                    (
+                OpCode::BitAnd
+                | OpCode::BitOr
+                | OpCode::BitXor
+                | OpCode::ShiftLeft
+                | OpCode::ShiftRight => match promote(self.pop(), self.pop()) {
+                    Operands::Other(Type::Number(b), Type::Number(a)) => {
+                        self.push(arithmetic(&byte, a, b)?)
+                    }
+                    Operands::Longs(b, a) => self.push(arithmetic(&byte, a, b)?),
+                    _ => return error!("Operands must be two integers."),
+                },
+                OpCode::BitNot => match self.pop() {
+                    Type::Number(val) => self.push(!val),
+                    Type::Long(val) => self.push(!val),
+                    _ => return error!("Operand must be an integer."),
+                },
+                OpCode::Add => match promote(self.peek(0), self.peek(1)) {
+                    Operands::Other(
                         Type::Object(ObjectPointer::String(b)),
                         Type::Object(ObjectPointer::String(a)),
                     ) if a.get_ref().is_some() && b.get_ref().is_some() => {
                         let b = b.get_ref().unwrap();
                         let a = a.get_ref().unwrap();
-                        // remove the leading '"'
-                        let b = &b[1..];
-                        // remove the trailing '"'
-                        let a = &a[..a.len() - 1];
-
-                        let s = [a, b].concat();
-                        let s = self.memory.allocate_string(&s);
+                        let s = [&a[..], &b[..]].concat();
+                        let s = self.allocate_string(&s);
                         self.pop();
                         self.pop();
 
                         self.push(s);
                     }
 
-                    (Type::Number(b), Type::Number(a)) => {
+                    Operands::Other(Type::Number(b), Type::Number(a)) => {
+                        let n = arithmetic(&byte, a, b)?;
+                        self.pop();
+                        self.pop();
+                        self.push(n);
+                    }
+                    Operands::Floats(b, a) => {
                         self.pop();
                         self.pop();
                         self.push(a + b);
                     }
+                    Operands::Longs(b, a) => {
+                        let n = arithmetic(&byte, a, b)?;
+                        self.pop();
+                        self.pop();
+                        self.push(n);
+                    }
+                    Operands::Other(Type::Number(b), Type::Char(a)) => {
+                        let c = shift_char(a, b)?;
+                        self.pop();
+                        self.pop();
+                        self.push(c);
+                    }
 
                     _ => {
                         return error!("Operands must be two numbers or two strings");
                     }
                 },
-                OpCode::Negate => {
-                    let val: i32 = self.pop().try_into()?;
-                    self.push(-val);
-                }
+                OpCode::Negate => match self.pop() {
+                    Type::Float(val) => self.push(-val),
+                    Type::Long(val) => {
+                        let Some(negated) = val.checked_neg() else {
+                            return error!("Integer overflow: -({}).", val);
+                        };
+                        self.push(negated);
+                    }
+                    val => {
+                        let val: i32 = val.try_into()?;
+                        let Some(negated) = val.checked_neg() else {
+                            return error!("Integer overflow: -({}).", val);
+                        };
+                        self.push(negated);
+                    }
+                },
                 OpCode::True => self.push(true),
                 OpCode::False => self.push(false),
                 OpCode::Nil => self.push(Type::Nil),
@@ -178,10 +718,18 @@ impl Vm {
                     let val = self.pop().is_falsy();
                     self.push(val);
                 }
-                OpCode::Equal => {
+                OpCode::Equal | OpCode::NotEqual => {
                     let b = self.pop();
                     let a = self.pop();
-                    self.push(a == b);
+                    let equal = match promote(a, b) {
+                        Operands::Floats(a, b) => a == b,
+                        Operands::Longs(a, b) => a == b,
+                        Operands::Other(a, b) => a == b,
+                    };
+                    match byte {
+                        OpCode::Equal => self.push(equal),
+                        _ => self.push(!equal),
+                    }
                 }
                 OpCode::Print => {
                     println!("{}", self.pop());
@@ -194,9 +742,112 @@ impl Vm {
     }
 }
 
+/// Checks `value` against the declared type of field `index` of `def`.
+fn check_field(def: &ObjStruct, index: usize, value: &Type) -> Result<()> {
+    let (name, id) = &def.fields[index];
+    if !id.matches(value) {
+        return error!(
+            "Field '{}' of {} expects {}, found {}.",
+            name,
+            def.name,
+            id,
+            value.type_id()
+        );
+    }
+    Ok(())
+}
+
+/// Applies the integer `op` to `a` and `b`, failing rather than
+/// overflowing `T` or dividing by zero.
+fn arithmetic<T>(op: &OpCode, a: T, b: T) -> Result<T>
+where
+    T: Copy + Display + Into<i128> + TryFrom<i128>,
+{
+    // Wide enough that no i64 operation but `**` can overflow it.
+    let (x, y): (i128, i128) = (a.into(), b.into());
+    let bits = mem::size_of::<T>() as i128 * 8;
+    let (result, symbol) = match op {
+        OpCode::Add => (Some(x + y), "+"),
+        OpCode::Subtract => (Some(x - y), "-"),
+        OpCode::Multiply => (Some(x * y), "*"),
+        OpCode::Divide | OpCode::Modulo if y == 0 => return error!("Division by zero."),
+        OpCode::Divide => (Some(x / y), "/"),
+        OpCode::Modulo => (Some(x % y), "%"),
+        OpCode::Power if y < 0 => return error!("Negative exponent: {} ** {}.", a, b),
+        OpCode::Power => (u32::try_from(y).ok().and_then(|y| x.checked_pow(y)), "**"),
+        OpCode::BitAnd => (Some(x & y), "&"),
+        OpCode::BitOr => (Some(x | y), "|"),
+        OpCode::BitXor => (Some(x ^ y), "^"),
+        OpCode::ShiftLeft | OpCode::ShiftRight if !(0..bits).contains(&y) => {
+            return error!("Shift amount {} out of range for {} bits.", b, bits)
+        }
+        // Bits shifted past the top are dropped, as in a hash.
+        OpCode::ShiftLeft => (Some((x << y) << (128 - bits) >> (128 - bits)), "<<"),
+        OpCode::ShiftRight => (Some(x >> y), ">>"),
+        _ => unreachable!(),
+    };
+    match result.and_then(|n| T::try_from(n).ok()) {
+        Some(n) => Ok(n),
+        None => error!("Integer overflow: {} {} {}.", a, symbol, b),
+    }
+}
+
+/// The operands of a binary operator, in the order they were given.
+enum Operands {
+    /// One is a float and the other a number.
+    Floats(f64, f64),
+    /// One is an i64 and the other an int.
+    Longs(i64, i64),
+    Other(Type, Type),
+}
+
+/// Promotes `a` and `b` to the wider of their number types.
+fn promote(a: Type, b: Type) -> Operands {
+    let float = |value: Type| match value {
+        Type::Float(n) => Some(n),
+        Type::Number(n) => Some(n as f64),
+        Type::Long(n) => Some(n as f64),
+        _ => None,
+    };
+    match (a, b) {
+        (Type::Float(_), _) | (_, Type::Float(_)) => match float(a).zip(float(b)) {
+            Some((a, b)) => Operands::Floats(a, b),
+            None => Operands::Other(a, b),
+        },
+        (Type::Long(a), Type::Long(b)) => Operands::Longs(a, b),
+        (Type::Long(a), Type::Number(b)) => Operands::Longs(a, b as i64),
+        (Type::Number(a), Type::Long(b)) => Operands::Longs(a as i64, b),
+        _ => Operands::Other(a, b),
+    }
+}
+
+/// The character `by` code points after `c`.
+fn shift_char(c: char, by: Number) -> Result<Type> {
+    match (c as Number)
+        .checked_add(by)
+        .and_then(|n| char::from_u32(n as u32))
+    {
+        Some(c) => Ok(c.into()),
+        None => error!(
+            "Character '{}' shifted by {} is not a valid character.",
+            c, by
+        ),
+    }
+}
+
 pub fn interpret(source: &str) -> Result<()> {
-    let chunk = compile(source)?;
+    VM.lock().memory.begin_compile();
+    let function = compile(source);
     let mut vm = VM.lock();
-    vm.ip = Ip::from(vm.chunk.insert(Box::pin(chunk)).as_ref().get_ref());
+    vm.memory.end_compile();
+    let function = function?;
+    vm.push(function);
+    let ObjectPointer::Closure(closure) = vm.allocate_object(ObjClosure::new(function, vec![]))
+    else {
+        unreachable!();
+    };
+    vm.pop();
+    vm.push(closure);
+    vm.call(closure, 0)?;
     vm.run()
 }
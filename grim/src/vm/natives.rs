@@ -0,0 +1,11 @@
+use super::{Result, Vm};
+use crate::lang_core::Type;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `clock()`: the seconds since the Unix epoch, as a float.
+pub(super) fn clock(_: &mut Vm, _: &[Type]) -> Result<Type> {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64());
+    Ok(elapsed.into())
+}
@@ -0,0 +1,113 @@
+use super::{Result, Vm, STACK_MAX};
+use crate::{
+    err::VmError,
+    lang_core::{objects::Pointable, prelude::*, types::string_method},
+};
+
+impl Vm {
+    /// Calls the built-in method `name` on the string below the
+    /// `arg_count` arguments on the stack.
+    pub(super) fn invoke_string(
+        &mut self,
+        string: StringPointer,
+        name: StringPointer,
+        arg_count: u8,
+    ) -> Result<()> {
+        let method = name.get_ref().expect("valid string");
+        let Some((params, _)) = string_method(method) else {
+            return error!("Undefined method '{}' on String.", name);
+        };
+        let arg_count = arg_count as usize;
+        if params.len() != arg_count {
+            return error!("Expected {} arguments but got {}.", params.len(), arg_count);
+        }
+        let args = self.stack[self.stack_top - arg_count..self.stack_top].to_vec();
+        for (param, arg) in params.iter().zip(&args) {
+            if !param.accepts(&arg.type_id()) {
+                return error!("Expected {}, found {}.", param, arg.type_id());
+            }
+        }
+        // The receiver stays on the stack, and so alive, until the end.
+        let s: &str = string.get_ref().expect("valid string");
+        let result: Type = match &**method {
+            "len" => (s.chars().count() as Number).into(),
+            "substr" => {
+                let (start, len) = (Number::try_from(args[0])?, Number::try_from(args[1])?);
+                let substr = substr(s, start, len)?;
+                self.allocate_string(substr).into()
+            }
+            "index_of" => {
+                let needle = str_arg(args[0]);
+                match s.find(needle) {
+                    Some(byte) => (s[..byte].chars().count() as Number).into(),
+                    None => Type::Number(-1),
+                }
+            }
+            "contains" => s.contains(str_arg(args[0])).into(),
+            "starts_with" => s.starts_with(str_arg(args[0])).into(),
+            "split" => self.split(s, str_arg(args[0]))?,
+            "trim" => self.allocate_string(s.trim()).into(),
+            "upper" => self.allocate_string(&s.to_uppercase()).into(),
+            "lower" => self.allocate_string(&s.to_lowercase()).into(),
+            "replace" => {
+                let replaced = s.replace(str_arg(args[0]), str_arg(args[1]));
+                self.allocate_string(&replaced).into()
+            }
+            "to_int" => match s.trim().parse::<Number>() {
+                Ok(n) => n.into(),
+                Err(_) => return error!("Can't convert '{}' to int.", s),
+            },
+            _ => unreachable!(),
+        };
+        self.stack_top -= arg_count + 1;
+        self.push(result);
+        Ok(())
+    }
+
+    /// Splits `s` around `separator` into an array of strings.
+    fn split(&mut self, s: &str, separator: &str) -> Result<Type> {
+        if separator.is_empty() {
+            return error!("Can't split on an empty separator.");
+        }
+        let count = s.matches(separator).count() + 1;
+        if STACK_MAX - self.stack_top < count {
+            return error!("Stack overflow.");
+        }
+        // The parts stay on the stack, where the collector sees them, until
+        // the array holding them is allocated.
+        for part in s.split(separator) {
+            let part = self.allocate_string(part);
+            self.push(part);
+        }
+        let parts = self.stack[self.stack_top - count..self.stack_top].to_vec();
+        let array = self.allocate_object(ObjArray::new(TypeId::String, parts));
+        self.stack_top -= count;
+        Ok(array.into())
+    }
+}
+
+/// The `len` characters of `s` from character `start` on.
+fn substr(s: &str, start: Number, len: Number) -> Result<&str> {
+    let count = s.chars().count();
+    let end = start as i64 + len as i64;
+    if start < 0 || len < 0 || end as usize > count {
+        return error!(
+            "Substring {}..{} out of range for string of length {}.",
+            start, end, count
+        );
+    }
+    let byte = |index: usize| {
+        s.char_indices()
+            .nth(index)
+            .map_or(s.len(), |(byte, _)| byte)
+    };
+    Ok(&s[byte(start as usize)..byte(end as usize)])
+}
+
+/// The text of a String argument, whose type the caller checked.
+fn str_arg(arg: Type) -> &'static str {
+    match arg {
+        Type::Object(ObjectPointer::String(s)) => s.get_ref().expect("valid string"),
+        _ => unreachable!(),
+    }
+}
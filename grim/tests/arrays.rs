@@ -0,0 +1,41 @@
+use grim::{interpret, Type, Vm, VmError, VM};
+use std::sync::Mutex;
+
+/// What grim passed to `record`, in order.
+static RECORDED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn record(_: &mut Vm, args: &[Type]) -> Result<Type, VmError> {
+    RECORDED.lock().unwrap().push(args[0].to_string());
+    Ok(Type::Nil)
+}
+
+#[test]
+fn arrays_hold_their_declared_element_type() {
+    {
+        let mut vm = VM.lock();
+        vm.init();
+        vm.define_native("record", 1, record);
+    }
+    let source = r#"
+        bind a -: [int?; 2] = [nil, nil];
+        a[0] = 1;
+        record(a);
+        bind b -: [int?; 2] = [1, 2];
+        b[0] = nil;
+        record(b);
+    "#;
+    interpret(source).expect("script runs");
+    assert_eq!(*RECORDED.lock().unwrap(), ["[1, nil]", "[nil, 2]"]);
+
+    let source = r#"
+        def set(xs -: [int; 2], v) { xs[0] = v; }
+        set([1, 2], "one");
+    "#;
+    let err = interpret(source).expect_err("a String is not an int");
+    VM.lock().reset_stack();
+    assert!(
+        err.to_string().contains("Expected int, found String."),
+        "{}",
+        err
+    );
+}
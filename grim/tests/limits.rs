@@ -0,0 +1,40 @@
+use grim::{interpret, VM};
+use std::sync::Mutex;
+
+/// Held by each test, since they all share the one VM.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// Runs `source`, which should fail with an error mentioning `message`.
+fn fails_with(source: &str, message: &str) {
+    let err = interpret(source).expect_err("script fails");
+    VM.lock().reset_stack();
+    assert!(err.to_string().contains(message), "{}", err);
+}
+
+#[test]
+fn full_constant_tables_are_reported() {
+    let _serial = SERIAL.lock().unwrap();
+    VM.lock().init();
+
+    let checks = "{ bind v -: String = x; } ".repeat(256);
+    let source = format!("def g(x) {{ {checks} {{ bind w -: int = x; }} }} g(\"oops\");");
+    fails_with(&source, "Too many type checks in one function.");
+
+    let globals: String = (0..300).map(|i| format!("bind v{i} = {i}; ")).collect();
+    fails_with(&globals, "Too many constants in one chunk.");
+}
+
+#[test]
+fn huge_ranges_are_rejected() {
+    let _serial = SERIAL.lock().unwrap();
+    VM.lock().init();
+
+    fails_with(
+        "bind r = [0..2000000000];",
+        "Range of 2000000001 elements is too large.",
+    );
+    fails_with(
+        "def id(x) { return x; } bind r = [0..id(2000000000)];",
+        "Range of 2000000001 elements is too large.",
+    );
+}
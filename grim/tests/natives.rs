@@ -0,0 +1,45 @@
+use grim::{interpret, Type, Vm, VmError, VM};
+use std::sync::Mutex;
+
+/// What grim passed to `record`, in order.
+static RECORDED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn add(_: &mut Vm, args: &[Type]) -> Result<Type, VmError> {
+    match args {
+        [Type::Number(a), Type::Number(b)] => Ok(Type::Number(a + b)),
+        _ => VmError::new("add takes two ints.".into()),
+    }
+}
+
+fn greet(vm: &mut Vm, args: &[Type]) -> Result<Type, VmError> {
+    let greeting = format!("hello, {}", args[0]);
+    Ok(vm.allocate_string(&greeting).into())
+}
+
+fn record(_: &mut Vm, args: &[Type]) -> Result<Type, VmError> {
+    RECORDED.lock().unwrap().push(args[0].to_string());
+    Ok(Type::Nil)
+}
+
+#[test]
+fn natives_are_callable_from_grim() {
+    {
+        let mut vm = VM.lock();
+        vm.init();
+        vm.define_native("add", 2, add);
+        vm.define_native("greet", 1, greet);
+        vm.define_native("record", 1, record);
+    }
+    let source = r#"
+        record(add(2, 3));
+        bind greeting -: String = greet("grim");
+        record(greeting);
+        record(greeting.len());
+    "#;
+    interpret(source).expect("script runs");
+    assert_eq!(*RECORDED.lock().unwrap(), ["5", "hello, grim", "11"]);
+
+    let err = interpret(r#"add(1, "one");"#).expect_err("add rejects a String");
+    VM.lock().reset_stack();
+    assert!(err.to_string().ends_with("add takes two ints."), "{}", err);
+}
@@ -0,0 +1,37 @@
+use grim::{interpret, Type, Vm, VmError, VM};
+use std::sync::Mutex;
+
+/// What grim passed to `record`, in order.
+static RECORDED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn record(_: &mut Vm, args: &[Type]) -> Result<Type, VmError> {
+    RECORDED.lock().unwrap().push(args[0].to_string());
+    Ok(Type::Nil)
+}
+
+#[test]
+fn cyclic_instances_print_without_recursing() {
+    {
+        let mut vm = VM.lock();
+        vm.init();
+        vm.define_native("record", 1, record);
+    }
+    let source = r#"
+        typedef struct Node { v -: int, next -: Node? };
+        bind a = Node(1, nil);
+        a.next = a;
+        record(a);
+        record("${a}");
+        a.next = Node(2, a);
+        record(a);
+    "#;
+    interpret(source).expect("script runs");
+    assert_eq!(
+        *RECORDED.lock().unwrap(),
+        [
+            "Node { v: 1, next: Node {...} }",
+            "Node { v: 1, next: Node {...} }",
+            "Node { v: 1, next: Node { v: 2, next: Node {...} } }",
+        ]
+    );
+}